@@ -0,0 +1,112 @@
+//Turns the declarations in src/ffi.rs into C: constants become defines, the callback types
+//function pointer typedefs and the functions prototypes, each with its comments. Shared by the
+//gen-header example, which writes the header, and tests/header.rs, which checks it's current.
+
+//Everything in src/ffi.rs above its tests
+pub fn generate_header() -> String {
+    let source = include_str!("../../src/ffi.rs");
+    let mut header = String::from("\
+// Generated from src/ffi.rs by `cargo run --example gen-header`; don't edit by hand.
+#ifndef EIGHTYEIGHTYEMU_H
+#define EIGHTYEIGHTYEMU_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+");
+    let mut comments = vec!();
+    for line in source.lines().take_while(|line| *line != "#[cfg(test)]") {
+        let declaration = if let Some(comment) = line.strip_prefix("//") {
+            comments.push(comment);
+            continue;
+        } else if line.starts_with("#[") {
+            continue;
+        } else if let Some(constant) = line.strip_prefix("pub const ") {
+            let (name, value) = constant.split_once(": u32 = ").unwrap();
+            format!("#define I8080_{} {}", name, shared_value(value.trim_end_matches(';')))
+        } else if let Some(alias) = line.strip_prefix("pub type ") {
+            let (name, function) = alias.split_once(" = extern \"C\" fn").unwrap();
+            let (parameters, result) = c_signature(function.trim_end_matches(';'));
+            format!("typedef {} (*{})({});", result, c_type_name(name), parameters)
+        } else if line.starts_with("pub struct ") {
+            "typedef struct i8080 i8080;".to_string()
+        } else if let Some((_, function)) = line.split_once("extern \"C\" fn ") {
+            let (name, signature) = function.split_at(function.find('(').unwrap());
+            let (parameters, result) = c_signature(signature.trim_end_matches(" {"));
+            let parameters = if parameters.is_empty() { "void".to_string() } else { parameters };
+            format!("{}{}{}({});", result, if result.ends_with('*') { "" } else { " " }, name, parameters)
+        } else {
+            comments.clear();
+            continue;
+        };
+        //Runs of defines stay together
+        if !(comments.is_empty() && declaration.starts_with("#define") && header.lines().last().is_some_and(|last| last.starts_with("#define"))) {
+            header.push('\n');
+        }
+        for comment in comments.drain(..) {
+            header.push_str(&format!("// {}\n", comment));
+        }
+        header.push_str(&declaration);
+        header.push('\n');
+    }
+    header.push_str("\n#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+    header
+}
+
+//A constant taken from src/exports.rs is written out with its value there
+fn shared_value(value: &str) -> &str {
+    let name = match value.strip_prefix("exports::") {
+        Some(name) => name,
+        None => return value,
+    };
+    let prefix = format!("pub const {}: u32 = ", name);
+    include_str!("../../src/exports.rs").lines().find_map(|line| line.strip_prefix(prefix.as_str()))
+        .unwrap_or_else(|| panic!("{} isn't a constant in src/exports.rs", name)).trim_end_matches(';')
+}
+
+//"(name: Type, ...) -> Result" as C parameters and result type
+fn c_signature(signature: &str) -> (String, String) {
+    let (parameters, result) = match signature.split_once(" -> ") {
+        Some((parameters, result)) => (parameters, c_type(result)),
+        None => (signature, "void".to_string()),
+    };
+    let parameters = parameters[1..parameters.len() - 1].split(", ").filter(|parameter| !parameter.is_empty()).map(|parameter| {
+        let (name, rust_type) = parameter.split_once(": ").unwrap();
+        let c_type = c_type(rust_type);
+        if c_type.ends_with('*') { format!("{}{}", c_type, name) } else { format!("{} {}", c_type, name) }
+    }).collect::<Vec<String>>();
+    (parameters.join(", "), result)
+}
+
+fn c_type(rust_type: &str) -> String {
+    if let Some(pointee) = rust_type.strip_prefix("*const ") {
+        return format!("const {} *", c_type(pointee));
+    }
+    if let Some(pointee) = rust_type.strip_prefix("*mut ") {
+        return format!("{} *", c_type(pointee));
+    }
+    match rust_type {
+        "u8" | "u16" | "u32" | "u64" | "i32" => format!("{}int{}_t", if rust_type.starts_with('u') { "u" } else { "" }, &rust_type[1..]),
+        "usize" => "size_t".to_string(),
+        "bool" => "bool".to_string(),
+        "c_void" => "void".to_string(),
+        "I8080" => "i8080".to_string(),
+        _ => c_type_name(rust_type.trim_start_matches("Option<").trim_end_matches('>')),
+    }
+}
+
+//InputCallback becomes i8080_input_callback
+fn c_type_name(name: &str) -> String {
+    let mut c_name = String::from("i8080");
+    for c in name.chars() {
+        if c.is_uppercase() {
+            c_name.push('_');
+        }
+        c_name.extend(c.to_lowercase());
+    }
+    c_name
+}
@@ -0,0 +1,13 @@
+//Writes include/eightyeightyemu.h from the declarations in src/ffi.rs; run it with
+//  cargo run --example gen-header
+//after changing them. tests/header.rs fails until the header has been written again.
+use std::fs;
+
+mod header;
+
+const HEADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/include/eightyeightyemu.h");
+
+fn main() {
+    fs::write(HEADER_PATH, header::generate_header()).expect("Unable to write the header, aborting.");
+    println!("Wrote {}", HEADER_PATH);
+}
@@ -0,0 +1,81 @@
+// Generated from src/ffi.rs by `cargo run --example gen-header`; don't edit by hand.
+#ifndef EIGHTYEIGHTYEMU_H
+#define EIGHTYEIGHTYEMU_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+// Bumped whenever a declaration in the header changes in a way that breaks existing callers
+#define I8080_ABI_VERSION 1
+
+// Registers are numbered as the 8080 encodes them, 0 to 7 being B C D E H L M A, then these
+#define I8080_REGISTER_FLAGS 8
+#define I8080_REGISTER_SP 9
+#define I8080_REGISTER_PC 10
+
+// Called for IN with the port number; returns the byte read
+typedef uint8_t (*i8080_input_callback)(void *user_data, uint8_t port);
+
+// Called for OUT with the port number and the byte written
+typedef void (*i8080_output_callback)(void *user_data, uint8_t port, uint8_t value);
+
+// A CPU with 64K of memory and its ports; opaque to C
+typedef struct i8080 i8080;
+
+// The ABI_VERSION the library was built with
+uint32_t i8080_abi_version(void);
+
+// A CPU at power on, with memory cleared and nothing on its ports. Never null.
+i8080 *i8080_new(void);
+
+// cpu may be null, which is ignored
+void i8080_free(i8080 *cpu);
+
+// cpu must not be null. Either callback may be null: reads then see 0 and writes go nowhere.
+void i8080_set_io(i8080 *cpu, i8080_input_callback input, i8080_output_callback output, void *user_data);
+
+// cpu must not be null. Copies len bytes into memory from addr, wrapping at the top of memory;
+// null bytes or a len of 0 loads nothing.
+void i8080_load(i8080 *cpu, uint16_t addr, const uint8_t *bytes, size_t len);
+
+// cpu must not be null
+uint8_t i8080_read_memory(const i8080 *cpu, uint16_t addr);
+
+// cpu must not be null
+void i8080_write_memory(i8080 *cpu, uint16_t addr, uint8_t value);
+
+// cpu must not be null. Runs one instruction and returns the cycles it took, or -1 without
+// touching the CPU if the opcode is one the 8080 doesn't define.
+int32_t i8080_step(i8080 *cpu);
+
+// cpu must not be null. Runs for at least cycles cycles, stopping early if the CPU halts or
+// reaches an undefined opcode, and returns how many it ran.
+uint64_t i8080_run(i8080 *cpu, uint64_t cycles);
+
+// cpu must not be null. Raises RST vector; returns whether the CPU had interrupts enabled and
+// so took it.
+bool i8080_interrupt(i8080 *cpu, uint8_t vector);
+
+// cpu must not be null
+bool i8080_halted(const i8080 *cpu);
+
+// cpu must not be null
+uint64_t i8080_cycles(const i8080 *cpu);
+
+// cpu must not be null. Register M reads the byte HL points at; an unknown register reads as 0.
+uint16_t i8080_get_register(const i8080 *cpu, uint32_t reg);
+
+// cpu must not be null. 8 bit registers take the low byte of value; setting an unknown
+// register does nothing.
+void i8080_set_register(i8080 *cpu, uint32_t reg, uint16_t value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
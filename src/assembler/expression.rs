@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use super::AssemblyError;
+use super::lexer::{Tok, Token};
+use super::object::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+    High,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Shl,
+    Shr,
+    And,
+    Or,
+    Xor,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String, usize),
+    Location,
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, usize),
+}
+
+//What a value is relative to: a segment whose load address the linker adds later,
+//or an external symbol it fills in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base {
+    Segment(Segment),
+    External(String),
+}
+
+pub const ABSOLUTE: Base = Base::Segment(Segment::Absolute);
+
+pub struct Context<'a> {
+    pub symbols: &'a HashMap<String, i64>,
+    pub bases: &'a HashMap<String, Base>,
+    pub location: i64,
+    pub segment: Segment,
+    pub line: usize,
+}
+
+//Values are kept in an i64 so intermediate results can go past 16 bits, but not past that
+fn overflow(context: &Context, column: usize) -> AssemblyError {
+    AssemblyError::new(context.line, column, "value overflows".to_string())
+}
+
+impl Expr {
+    pub fn eval(&self, context: &Context) -> Result<i64, AssemblyError> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Location => Ok(context.location),
+            Expr::Symbol(name, column) => context.symbols.get(name).cloned().ok_or_else(||
+                AssemblyError::new(context.line, *column, format!("undefined symbol {}", name))),
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(context)?;
+                Ok(match op {
+                    UnaryOp::Negate => value.checked_neg().ok_or_else(|| overflow(context, operand.column()))?,
+                    UnaryOp::Not => !value & 0xffff,
+                    UnaryOp::High => (value >> 8) & 0xff,
+                    UnaryOp::Low => value & 0xff,
+                })
+            },
+            Expr::Binary(op, lhs, rhs, column) => {
+                let lhs = lhs.eval(context)?;
+                let rhs = rhs.eval(context)?;
+                //comparisons follow Intel convention: all ones for true, zero for false
+                let truth = |b: bool| if b { 0xffff } else { 0 };
+                Ok(match op {
+                    BinaryOp::Add => lhs.checked_add(rhs).ok_or_else(|| overflow(context, *column))?,
+                    BinaryOp::Sub => lhs.checked_sub(rhs).ok_or_else(|| overflow(context, *column))?,
+                    BinaryOp::Mul => lhs.checked_mul(rhs).ok_or_else(|| overflow(context, *column))?,
+                    BinaryOp::Div | BinaryOp::Mod if rhs == 0 =>
+                        return Err(AssemblyError::new(context.line, *column, "division by zero".to_string())),
+                    BinaryOp::Div => lhs.checked_div(rhs).ok_or_else(|| overflow(context, *column))?,
+                    BinaryOp::Mod => lhs.checked_rem(rhs).ok_or_else(|| overflow(context, *column))?,
+                    BinaryOp::Shl => (lhs << (rhs & 0x1f)) & 0xffff,
+                    BinaryOp::Shr => (lhs & 0xffff) >> (rhs & 0x1f),
+                    BinaryOp::And => lhs & rhs & 0xffff,
+                    BinaryOp::Or => (lhs | rhs) & 0xffff,
+                    BinaryOp::Xor => (lhs ^ rhs) & 0xffff,
+                    BinaryOp::Eq => truth(lhs & 0xffff == rhs & 0xffff),
+                    BinaryOp::Ne => truth(lhs & 0xffff != rhs & 0xffff),
+                    BinaryOp::Lt => truth(lhs < rhs),
+                    BinaryOp::Le => truth(lhs <= rhs),
+                    BinaryOp::Gt => truth(lhs > rhs),
+                    BinaryOp::Ge => truth(lhs >= rhs),
+                })
+            },
+        }
+    }
+
+    //Sums and differences keep track of what a value is relative to; every other
+    //operator needs absolute operands.
+    pub fn base(&self, context: &Context) -> Result<Base, AssemblyError> {
+        match self {
+            Expr::Number(_) => Ok(ABSOLUTE),
+            Expr::Location => Ok(Base::Segment(context.segment)),
+            Expr::Symbol(name, _) => Ok(context.bases.get(name).cloned().unwrap_or(ABSOLUTE)),
+            Expr::Unary(_, operand) => match operand.base(context)? {
+                ABSOLUTE => Ok(ABSOLUTE),
+                _ => Err(AssemblyError::new(context.line, operand.column(),
+                    "HIGH, LOW, NOT and negation need an absolute value".to_string())),
+            },
+            Expr::Binary(op, lhs, rhs, column) => {
+                let lhs = lhs.base(context)?;
+                let rhs = rhs.base(context)?;
+                match op {
+                    _ if lhs == ABSOLUTE && rhs == ABSOLUTE => Ok(ABSOLUTE),
+                    BinaryOp::Add | BinaryOp::Sub if rhs == ABSOLUTE => Ok(lhs),
+                    BinaryOp::Add if lhs == ABSOLUTE => Ok(rhs),
+                    //the distance between two labels in one segment doesn't move with it
+                    BinaryOp::Sub if lhs == rhs && matches!(lhs, Base::Segment(_)) => Ok(ABSOLUTE),
+                    _ => Err(AssemblyError::new(context.line, *column,
+                        "relocatable values can only be offset by absolute ones".to_string())),
+                }
+            },
+        }
+    }
+
+    fn column(&self) -> usize {
+        match self {
+            Expr::Symbol(_, column) | Expr::Binary(_, _, _, column) => *column,
+            Expr::Unary(_, operand) => operand.column(),
+            Expr::Number(_) | Expr::Location => 1,
+        }
+    }
+}
+
+//Parses a whole operand; anything left over after the expression is an error.
+pub fn parse(tokens: &[Tok], line: usize) -> Result<Expr, AssemblyError> {
+    let mut parser = Parser { tokens, pos: 0, line };
+    let expr = parser.parse_or()?;
+    match parser.peek() {
+        Some(tok) => Err(AssemblyError::new(line, tok.column, format!("unexpected {}", describe(&tok.token)))),
+        None => Ok(expr),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Tok],
+    pos: usize,
+    line: usize,
+}
+
+//Intel precedence, loosest first: OR/XOR, AND, NOT, relations, +/-, * / MOD SHL SHR, unary
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_keyword(&self) -> Option<&'a str> {
+        match self.peek() {
+            Some(Tok { token: Token::Ident(name), .. }) => Some(name.as_str()),
+            _ => None,
+        }
+    }
+
+    fn end_column(&self) -> usize {
+        self.tokens.last().map_or(1, |tok| tok.column + 1)
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, AssemblyError> {
+        let mut lhs = self.parse_and()?;
+        loop {
+            let op = match self.peek_keyword() {
+                Some("OR") => BinaryOp::Or,
+                Some("XOR") => BinaryOp::Xor,
+                _ => return Ok(lhs),
+            };
+            let column = self.tokens[self.pos].column;
+            self.pos += 1;
+            let rhs = self.parse_and()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), column);
+        }
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, AssemblyError> {
+        let mut lhs = self.parse_not()?;
+        while self.peek_keyword() == Some("AND") {
+            let column = self.tokens[self.pos].column;
+            self.pos += 1;
+            let rhs = self.parse_not()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(rhs), column);
+        }
+        Ok(lhs)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, AssemblyError> {
+        if self.peek_keyword() == Some("NOT") {
+            self.pos += 1;
+            let operand = self.parse_not()?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(operand)));
+        }
+        self.parse_relation()
+    }
+
+    fn parse_relation(&mut self) -> Result<Expr, AssemblyError> {
+        let mut lhs = self.parse_additive()?;
+        loop {
+            let op = match self.peek_keyword() {
+                Some("EQ") => BinaryOp::Eq,
+                Some("NE") => BinaryOp::Ne,
+                Some("LT") => BinaryOp::Lt,
+                Some("LE") => BinaryOp::Le,
+                Some("GT") => BinaryOp::Gt,
+                Some("GE") => BinaryOp::Ge,
+                _ => return Ok(lhs),
+            };
+            let column = self.tokens[self.pos].column;
+            self.pos += 1;
+            let rhs = self.parse_additive()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), column);
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, AssemblyError> {
+        let mut lhs = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok { token: Token::Plus, .. }) => BinaryOp::Add,
+                Some(Tok { token: Token::Minus, .. }) => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            let column = self.tokens[self.pos].column;
+            self.pos += 1;
+            let rhs = self.parse_multiplicative()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), column);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, AssemblyError> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok { token: Token::Star, .. }) => BinaryOp::Mul,
+                Some(Tok { token: Token::Slash, .. }) => BinaryOp::Div,
+                Some(Tok { token: Token::Ident(name), .. }) => match name.as_str() {
+                    "MOD" => BinaryOp::Mod,
+                    "SHL" => BinaryOp::Shl,
+                    "SHR" => BinaryOp::Shr,
+                    _ => return Ok(lhs),
+                },
+                _ => return Ok(lhs),
+            };
+            let column = self.tokens[self.pos].column;
+            self.pos += 1;
+            let rhs = self.parse_unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), column);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, AssemblyError> {
+        let op = match self.peek() {
+            Some(Tok { token: Token::Minus, .. }) => Some(UnaryOp::Negate),
+            Some(Tok { token: Token::Plus, .. }) => None,
+            Some(Tok { token: Token::Ident(name), .. }) if name == "HIGH" => Some(UnaryOp::High),
+            Some(Tok { token: Token::Ident(name), .. }) if name == "LOW" => Some(UnaryOp::Low),
+            _ => return self.parse_primary(),
+        };
+        self.pos += 1;
+        let operand = self.parse_unary()?;
+        Ok(match op {
+            Some(op) => Expr::Unary(op, Box::new(operand)),
+            None => operand,
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, AssemblyError> {
+        let tok = match self.peek() {
+            Some(tok) => tok,
+            None => return Err(AssemblyError::new(self.line, self.end_column(), "expected an expression".to_string())),
+        };
+        self.pos += 1;
+        match tok.token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Dollar => Ok(Expr::Location),
+            Token::Ident(ref name) => Ok(Expr::Symbol(name.clone(), tok.column)),
+            Token::Str(ref text) => {
+                //one or two characters make a value, the first in the high byte
+                let bytes = text.as_bytes();
+                match bytes.len() {
+                    1 => Ok(Expr::Number(bytes[0] as i64)),
+                    2 => Ok(Expr::Number(((bytes[0] as i64) << 8) | bytes[1] as i64)),
+                    _ => Err(AssemblyError::new(self.line, tok.column,
+                        "only one or two character strings can be used as a value".to_string())),
+                }
+            },
+            Token::LParen => {
+                let inner = self.parse_or()?;
+                match self.peek() {
+                    Some(Tok { token: Token::RParen, .. }) => {
+                        self.pos += 1;
+                        Ok(inner)
+                    },
+                    Some(other) => Err(AssemblyError::new(self.line, other.column, "expected ')'".to_string())),
+                    None => Err(AssemblyError::new(self.line, self.end_column(), "expected ')'".to_string())),
+                }
+            },
+            ref other => Err(AssemblyError::new(self.line, tok.column, format!("unexpected {}", describe(other)))),
+        }
+    }
+}
+
+pub fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("'{}'", name),
+        Token::Number(value) => format!("number {}", value),
+        Token::Str(text) => format!("string '{}'", text),
+        Token::Comma => "','".to_string(),
+        Token::Colon => "':'".to_string(),
+        Token::LParen => "'('".to_string(),
+        Token::RParen => "')'".to_string(),
+        Token::Plus => "'+'".to_string(),
+        Token::Minus => "'-'".to_string(),
+        Token::Star => "'*'".to_string(),
+        Token::Slash => "'/'".to_string(),
+        Token::Dollar => "'$'".to_string(),
+        Token::Other(c) => format!("'{}'", c),
+    }
+}
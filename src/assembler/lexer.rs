@@ -0,0 +1,127 @@
+use super::AssemblyError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Ident(String),
+    Number(i64),
+    Str(String),
+    Comma,
+    Colon,
+    LParen,
+    RParen,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Dollar,
+    Other(char),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tok {
+    pub token: Token,
+    pub column: usize,
+}
+
+//Splits one source line into tokens, dropping the trailing comment.
+//Columns are 1-based so they can be reported straight back to the user.
+pub fn tokenize(line: usize, text: &str) -> Result<Vec<Tok>, AssemblyError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec!();
+    let mut i = 0;
+    //old-style sources mark whole-line comments with a leading asterisk
+    if chars.first() == Some(&'*') {
+        return Ok(tokens);
+    }
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c == ';' {
+            break;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let literal: String = chars[start..i].iter().collect();
+            Token::Number(parse_number(&literal).ok_or_else(||
+                AssemblyError::new(line, column, format!("invalid number {}", literal)))?)
+        } else if is_ident_start(c) {
+            let start = i;
+            while i < chars.len() && is_ident_char(chars[i]) {
+                i += 1;
+            }
+            Token::Ident(chars[start..i].iter().collect::<String>().to_ascii_uppercase())
+        } else if c == '\'' || c == '"' {
+            let mut value = String::new();
+            i += 1;
+            loop {
+                if i >= chars.len() {
+                    return Err(AssemblyError::new(line, column, "unterminated string".to_string()));
+                }
+                if chars[i] == c {
+                    //a doubled quote stands for the quote character itself
+                    if chars.get(i + 1) == Some(&c) {
+                        value.push(c);
+                        i += 2;
+                        continue;
+                    }
+                    i += 1;
+                    break;
+                }
+                value.push(chars[i]);
+                i += 1;
+            }
+            Token::Str(value)
+        } else {
+            i += 1;
+            match c {
+                ',' => Token::Comma,
+                ':' => Token::Colon,
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                '+' => Token::Plus,
+                '-' => Token::Minus,
+                '*' => Token::Star,
+                '/' => Token::Slash,
+                '$' => Token::Dollar,
+                //left for the parser to reject, so macro arguments can carry '<', '&' and friends
+                _ => Token::Other(c),
+            }
+        };
+        tokens.push(Tok { token, column });
+    }
+    Ok(tokens)
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_' || c == '?' || c == '@' || c == '.'
+}
+
+fn is_ident_char(c: char) -> bool {
+    is_ident_start(c) || c.is_ascii_digit() || c == '$'
+}
+
+//Intel numbers carry their radix as a suffix: 0FFH, 1010B, 17O/17Q, 99D
+fn parse_number(literal: &str) -> Option<i64> {
+    let upper = literal.to_ascii_uppercase();
+    if let Some(digits) = upper.strip_prefix("0X") {
+        return i64::from_str_radix(digits, 16).ok();
+    }
+    let (digits, radix) = match upper.chars().last() {
+        Some('H') => (&upper[..upper.len() - 1], 16),
+        Some('B') => (&upper[..upper.len() - 1], 2),
+        Some('O') | Some('Q') => (&upper[..upper.len() - 1], 8),
+        Some('D') => (&upper[..upper.len() - 1], 10),
+        _ => (&upper[..], 10),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+    i64::from_str_radix(digits, radix).ok()
+}
@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+//A line waiting to be assembled, tagged with where it came from for errors and the listing
+#[derive(Debug, Clone)]
+pub struct InputLine {
+    pub text: String,
+    pub origin: usize,
+    pub expanded: bool,
+}
+
+pub struct Macro {
+    pub params: Vec<String>,
+    pub body: Vec<InputLine>,
+}
+
+impl Macro {
+    //Substitutes the call's arguments for the parameters; LOCAL names get a fresh
+    //??nnnn spelling per expansion so labels inside the body don't collide.
+    pub fn expand(&self, args: &[String], origin: usize, next_local: &mut usize) -> Vec<InputLine> {
+        let mut replacements = HashMap::new();
+        for (index, param) in self.params.iter().enumerate() {
+            replacements.insert(param.clone(), args.get(index).cloned().unwrap_or_default());
+        }
+        let mut lines = vec!();
+        for line in &self.body {
+            if let Some(locals) = local_names(&line.text) {
+                for local in locals {
+                    *next_local += 1;
+                    replacements.insert(local, format!("??{:04}", next_local));
+                }
+                continue;
+            }
+            lines.push(InputLine { text: substitute(&line.text, &replacements), origin, expanded: true });
+        }
+        lines
+    }
+}
+
+fn local_names(text: &str) -> Option<Vec<String>> {
+    let trimmed = text.trim_start();
+    let keyword = trimmed.get(..5)?;
+    if !keyword.eq_ignore_ascii_case("LOCAL") || !trimmed[5..].starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some(split_arguments(&trimmed[5..]).iter().map(|name| name.to_ascii_uppercase()).collect())
+}
+
+//Replaces whole identifiers outside quotes. Inside quotes, and to glue a parameter onto
+//surrounding text, the parameter has to be marked with '&', which is dropped afterwards.
+pub fn substitute(text: &str, replacements: &HashMap<String, String>) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::new();
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if quote.is_none() && c == ';' {
+            result.extend(&chars[i..]);
+            break;
+        }
+        if c == '\'' || c == '"' {
+            quote = match quote {
+                Some(open) if open == c => None,
+                None => Some(c),
+                other => other,
+            };
+            result.push(c);
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' || c == '?' || c == '@' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '?' || chars[i] == '@') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            let joined_before = start > 0 && chars[start - 1] == '&';
+            let joined_after = chars.get(i) == Some(&'&');
+            match replacements.get(&word.to_ascii_uppercase()) {
+                Some(replacement) if quote.is_none() || joined_before || joined_after => {
+                    if joined_before {
+                        result.pop();
+                    }
+                    result.push_str(replacement);
+                    if joined_after {
+                        i += 1;
+                    }
+                },
+                _ => result.push_str(&word),
+            }
+            continue;
+        }
+        result.push(c);
+        i += 1;
+    }
+    result
+}
+
+//Splits macro arguments on top-level commas. Quotes and parentheses are kept intact and
+//angle brackets group an argument that itself contains commas.
+pub fn split_arguments(text: &str) -> Vec<String> {
+    let mut args = vec!();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut bracketed = false;
+    for c in text.chars() {
+        if let Some(open) = quote {
+            current.push(c);
+            if c == open {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            ';' => break,
+            '\'' | '"' => {
+                quote = Some(c);
+                current.push(c);
+            },
+            '(' => {
+                depth += 1;
+                current.push(c);
+            },
+            ')' => {
+                depth -= 1;
+                current.push(c);
+            },
+            '<' if !bracketed && depth == 0 && current.trim().is_empty() => bracketed = true,
+            '>' if bracketed => bracketed = false,
+            ',' if depth == 0 && !bracketed => {
+                args.push(current.trim().to_string());
+                current.clear();
+            },
+            _ => current.push(c),
+        }
+    }
+    if !current.trim().is_empty() || !args.is_empty() {
+        args.push(current.trim().to_string());
+    }
+    args
+}
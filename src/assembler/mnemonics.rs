@@ -0,0 +1,286 @@
+use cpu::instruction::Instruction;
+use cpu::register::{Register, RegisterPair};
+use cpu::condition::ConditionOp;
+
+use super::{AssemblyError, Operand};
+use super::expression::{self, Base, Context};
+use super::lexer::{Tok, Token};
+
+//The operand layout an instruction expects, which also fixes its encoded size
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    Implied,
+    Reg,
+    RegReg,
+    RegImm,
+    Imm,
+    Pair,
+    PairImm,
+    IndexPair,
+    StackPair,
+    Addr,
+    Restart,
+}
+
+impl Shape {
+    pub fn size(self) -> u16 {
+        match self {
+            Shape::RegImm | Shape::Imm => 2,
+            Shape::PairImm | Shape::Addr => 3,
+            _ => 1,
+        }
+    }
+}
+
+pub fn lookup(mnemonic: &str) -> Option<Shape> {
+    let shape = match mnemonic {
+        "XCHG" | "DAA" | "RLC" | "RRC" | "RAL" | "RAR" | "RIM" | "SIM" | "RET" | "CMA" | "CMC" | "STC" |
+        "PCHL" | "XTHL" | "SPHL" | "EI" | "DI" | "HLT" | "NOP" => Shape::Implied,
+        "ADD" | "ADC" | "SUB" | "SBB" | "ANA" | "ORA" | "XRA" | "CMP" | "INR" | "DCR" => Shape::Reg,
+        "MOV" => Shape::RegReg,
+        "MVI" => Shape::RegImm,
+        "ADI" | "ACI" | "SUI" | "SBI" | "ANI" | "ORI" | "XRI" | "CPI" | "IN" | "OUT" => Shape::Imm,
+        "INX" | "DCX" | "DAD" => Shape::Pair,
+        "LXI" => Shape::PairImm,
+        "LDAX" | "STAX" => Shape::IndexPair,
+        "PUSH" | "POP" => Shape::StackPair,
+        "LDA" | "STA" | "LHLD" | "SHLD" | "JMP" | "CALL" => Shape::Addr,
+        "RST" => Shape::Restart,
+        _ => return match split_condition(mnemonic) {
+            Some(('R', _)) => Some(Shape::Implied),
+            Some(_) => Some(Shape::Addr),
+            None => None,
+        },
+    };
+    Some(shape)
+}
+
+//Conditional jumps, calls and returns are spelled as J/C/R followed by the condition
+fn split_condition(mnemonic: &str) -> Option<(char, ConditionOp)> {
+    let mut chars = mnemonic.chars();
+    let prefix = chars.next()?;
+    if prefix != 'J' && prefix != 'C' && prefix != 'R' {
+        return None;
+    }
+    let condition = match chars.as_str() {
+        "NZ" => ConditionOp::NZ,
+        "Z" => ConditionOp::Z,
+        "NC" => ConditionOp::NC,
+        "C" => ConditionOp::C,
+        "PO" => ConditionOp::PO,
+        "PE" => ConditionOp::PE,
+        "P" => ConditionOp::P,
+        "M" => ConditionOp::M,
+        _ => return None,
+    };
+    Some((prefix, condition))
+}
+
+pub fn build(mnemonic: &str, column: usize, operands: &[Operand], context: &Context) -> Result<Instruction, AssemblyError> {
+    let shape = match lookup(mnemonic) {
+        Some(shape) => shape,
+        None => return Err(AssemblyError::new(context.line, column, format!("unknown instruction {}", mnemonic))),
+    };
+    let expected = match shape {
+        Shape::Implied => 0,
+        Shape::RegReg | Shape::RegImm | Shape::PairImm => 2,
+        _ => 1,
+    };
+    if operands.len() != expected {
+        return Err(AssemblyError::new(context.line, column,
+            format!("{} takes {} operand{}, found {}", mnemonic, expected, if expected == 1 { "" } else { "s" }, operands.len())));
+    }
+    let instruction = match (shape, mnemonic) {
+        (Shape::Implied, "XCHG") => Instruction::XCHG,
+        (Shape::Implied, "DAA") => Instruction::DAA,
+        (Shape::Implied, "RLC") => Instruction::RLC,
+        (Shape::Implied, "RRC") => Instruction::RRC,
+        (Shape::Implied, "RAL") => Instruction::RAL,
+        (Shape::Implied, "RAR") => Instruction::RAR,
+        (Shape::Implied, "RIM") => Instruction::RIM,
+        (Shape::Implied, "SIM") => Instruction::SIM,
+        (Shape::Implied, "RET") => Instruction::RET,
+        (Shape::Implied, "CMA") => Instruction::CMA,
+        (Shape::Implied, "CMC") => Instruction::CMC,
+        (Shape::Implied, "STC") => Instruction::STC,
+        (Shape::Implied, "PCHL") => Instruction::PCHL,
+        (Shape::Implied, "XTHL") => Instruction::XTHL,
+        (Shape::Implied, "SPHL") => Instruction::SPHL,
+        (Shape::Implied, "EI") => Instruction::EI,
+        (Shape::Implied, "DI") => Instruction::DI,
+        (Shape::Implied, "HLT") => Instruction::HLT,
+        (Shape::Implied, "NOP") => Instruction::NOP,
+        (Shape::Reg, "INR") => Instruction::INR(register(&operands[0], context)?),
+        (Shape::Reg, "DCR") => Instruction::DCR(register(&operands[0], context)?),
+        (Shape::Reg, _) => {
+            let reg = register(&operands[0], context)?;
+            match mnemonic {
+                "ADD" => Instruction::ADD(reg),
+                "ADC" => Instruction::ADC(reg),
+                "SUB" => Instruction::SUB(reg),
+                "SBB" => Instruction::SBB(reg),
+                "ANA" => Instruction::ANA(reg),
+                "ORA" => Instruction::ORA(reg),
+                "XRA" => Instruction::XRA(reg),
+                _ => Instruction::CMP(reg),
+            }
+        },
+        (Shape::RegReg, _) => {
+            let dst = register(&operands[0], context)?;
+            let src = register(&operands[1], context)?;
+            //the MOV M,M slot is taken by HLT
+            if dst == Register::M && src == Register::M {
+                return Err(AssemblyError::new(context.line, operands[0].column, "MOV M,M is not a valid instruction".to_string()));
+            }
+            Instruction::MOV(dst, src)
+        },
+        (Shape::RegImm, _) => Instruction::MVI(register(&operands[0], context)?, byte_value(&operands[1], context)?),
+        (Shape::Imm, _) => {
+            let val = byte_value(&operands[0], context)?;
+            match mnemonic {
+                "ADI" => Instruction::ADI(val),
+                "ACI" => Instruction::ACI(val),
+                "SUI" => Instruction::SUI(val),
+                "SBI" => Instruction::SBI(val),
+                "ANI" => Instruction::ANI(val),
+                "ORI" => Instruction::ORI(val),
+                "XRI" => Instruction::XRI(val),
+                "CPI" => Instruction::CPI(val),
+                "IN" => Instruction::IN(val),
+                _ => Instruction::OUT(val),
+            }
+        },
+        (Shape::Pair, _) => {
+            let pair = register_pair(&operands[0], context)?;
+            match mnemonic {
+                "INX" => Instruction::INX(pair),
+                "DCX" => Instruction::DCX(pair),
+                _ => Instruction::DAD(pair),
+            }
+        },
+        (Shape::PairImm, _) => {
+            let pair = register_pair(&operands[0], context)?;
+            let val = word_value(&operands[1], context)?;
+            Instruction::LXI(pair, ((val >> 8) as u8, val as u8))
+        },
+        (Shape::IndexPair, _) => {
+            let pair = register_pair(&operands[0], context)?;
+            if pair != RegisterPair::BC && pair != RegisterPair::DE {
+                return Err(AssemblyError::new(context.line, operands[0].column,
+                    format!("{} only accepts register pairs B and D", mnemonic)));
+            }
+            if mnemonic == "LDAX" { Instruction::LDAX(pair) } else { Instruction::STAX(pair) }
+        },
+        (Shape::StackPair, _) => {
+            let is_push = mnemonic == "PUSH";
+            if single_ident(&operands[0]) == Some("PSW") {
+                if is_push { Instruction::PUSH_PSW } else { Instruction::POP_PSW }
+            } else {
+                let pair = register_pair(&operands[0], context)?;
+                if pair == RegisterPair::SP {
+                    return Err(AssemblyError::new(context.line, operands[0].column,
+                        format!("{} only accepts B, D, H or PSW", mnemonic)));
+                }
+                if is_push { Instruction::PUSH(pair) } else { Instruction::POP(pair) }
+            }
+        },
+        (Shape::Restart, _) => {
+            let vector = value(&operands[0], context)?;
+            if !(0..=7).contains(&vector) {
+                return Err(AssemblyError::new(context.line, operands[0].column,
+                    format!("restart vector {} is not between 0 and 7", vector)));
+            }
+            Instruction::RST(vector as u8)
+        },
+        (Shape::Addr, _) => {
+            let addr = word_value(&operands[0], context)?;
+            match mnemonic {
+                "LDA" => Instruction::LDA(addr),
+                "STA" => Instruction::STA(addr),
+                "LHLD" => Instruction::LHLD(addr),
+                "SHLD" => Instruction::SHLD(addr),
+                "JMP" => Instruction::JMP(addr),
+                "CALL" => Instruction::CALL(addr),
+                _ => match split_condition(mnemonic) {
+                    Some(('J', condition)) => Instruction::JCOND(condition, addr),
+                    Some((_, condition)) => Instruction::CCOND(condition, addr),
+                    None => unreachable!(),
+                },
+            }
+        },
+        (Shape::Implied, _) => match split_condition(mnemonic) {
+            Some((_, condition)) => Instruction::RETCOND(condition),
+            None => unreachable!(),
+        },
+    };
+    Ok(instruction)
+}
+
+//The operand that ends up as the instruction's address or 16-bit immediate, if any
+pub fn word_operand<'a>(mnemonic: &str, operands: &'a [Operand]) -> Option<&'a Operand> {
+    match lookup(mnemonic) {
+        Some(Shape::Addr) => operands.first(),
+        Some(Shape::PairImm) => operands.get(1),
+        _ => None,
+    }
+}
+
+fn single_ident(operand: &Operand) -> Option<&str> {
+    match operand.tokens.as_slice() {
+        [Tok { token: Token::Ident(name), .. }] => Some(name.as_str()),
+        _ => None,
+    }
+}
+
+fn register(operand: &Operand, context: &Context) -> Result<Register, AssemblyError> {
+    let reg = match single_ident(operand) {
+        Some("A") => Register::A,
+        Some("B") => Register::B,
+        Some("C") => Register::C,
+        Some("D") => Register::D,
+        Some("E") => Register::E,
+        Some("H") => Register::H,
+        Some("L") => Register::L,
+        Some("M") => Register::M,
+        _ => return Err(AssemblyError::new(context.line, operand.column, "expected a register".to_string())),
+    };
+    Ok(reg)
+}
+
+fn register_pair(operand: &Operand, context: &Context) -> Result<RegisterPair, AssemblyError> {
+    let pair = match single_ident(operand) {
+        Some("B") => RegisterPair::BC,
+        Some("D") => RegisterPair::DE,
+        Some("H") => RegisterPair::HL,
+        Some("SP") => RegisterPair::SP,
+        _ => return Err(AssemblyError::new(context.line, operand.column, "expected a register pair".to_string())),
+    };
+    Ok(pair)
+}
+
+pub fn value(operand: &Operand, context: &Context) -> Result<i64, AssemblyError> {
+    expression::parse(&operand.tokens, context.line)?.eval(context)
+}
+
+pub fn base(operand: &Operand, context: &Context) -> Result<Base, AssemblyError> {
+    expression::parse(&operand.tokens, context.line)?.base(context)
+}
+
+pub fn byte_value(operand: &Operand, context: &Context) -> Result<u8, AssemblyError> {
+    let val = value(operand, context)?;
+    if base(operand, context)? != expression::ABSOLUTE {
+        return Err(AssemblyError::new(context.line, operand.column, "a relocatable value does not fit in a byte".to_string()));
+    }
+    if !(-256..=255).contains(&val) {
+        return Err(AssemblyError::new(context.line, operand.column, format!("value {} does not fit in a byte", val)));
+    }
+    Ok(val as u8)
+}
+
+pub fn word_value(operand: &Operand, context: &Context) -> Result<u16, AssemblyError> {
+    let val = value(operand, context)?;
+    if !(-65536..=65535).contains(&val) {
+        return Err(AssemblyError::new(context.line, operand.column, format!("value {} does not fit in a word", val)));
+    }
+    Ok(val as u16)
+}
@@ -0,0 +1,1015 @@
+mod lexer;
+mod expression;
+mod macros;
+mod mnemonics;
+mod output;
+pub mod object;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cpu::Address;
+
+use self::expression::{Base, Context, ABSOLUTE};
+use self::lexer::{Tok, Token};
+use self::macros::{InputLine, Macro};
+use self::object::{Segment, MAX_NAME_LENGTH};
+
+pub use self::output::{image, write_hex};
+
+const DIRECTIVES: [&str; 21] = ["ORG", "EQU", "SET", "DB", "DW", "DS", "END",
+    "MACRO", "ENDM", "EXITM", "LOCAL", "REPT", "IF", "ELSE", "ENDIF",
+    "ASEG", "CSEG", "DSEG", "PUBLIC", "EXTRN", "NAME"];
+const INCLUDE: &str = "INCLUDE";
+const MAX_NESTING: usize = 64;
+//Each repeat is expanded up front, and more than one per byte of memory can't be meant
+const MAX_REPEATS: i64 = 0x10000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssemblyError {
+    pub file: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl AssemblyError {
+    pub fn new(line: usize, column: usize, message: String) -> AssemblyError {
+        AssemblyError { file: None, line, column, message }
+    }
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}: ", file)?;
+        }
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for AssemblyError {}
+
+pub struct Operand {
+    pub tokens: Vec<Tok>,
+    pub column: usize,
+}
+
+struct Statement {
+    label: Option<(String, usize)>,
+    operation: Option<(String, usize)>,
+    operands: Vec<Operand>,
+}
+
+//Lines are numbered internally by an origin id, which maps back to the file and
+//line they were read from once assembly is over.
+struct Origin {
+    file: Option<usize>,
+    line: usize,
+}
+
+struct SourceLine {
+    number: usize,
+    text: String,
+    expanded: bool,
+    location: i64,
+    segment: Segment,
+    set_value: Option<(i64, Base)>,
+    statement: Statement,
+}
+
+pub struct ListedLine {
+    pub number: usize,
+    pub text: String,
+    pub expanded: bool,
+    pub addr: Option<Address>,
+    pub segment: Segment,
+    pub bytes: Vec<u8>,
+    //words within the bytes that the linker still has to fix up
+    pub relocations: Vec<(usize, Base)>,
+    pub value: Option<u16>,
+}
+
+//The result of a successful assembly: every source line with the bytes it produced,
+//plus the final symbol table. Addresses in CSEG and DSEG are offsets into the segment.
+pub struct Assembly {
+    pub lines: Vec<ListedLine>,
+    pub symbols: BTreeMap<String, u16>,
+    pub start: Option<Address>,
+    pub start_segment: Segment,
+    pub name: Option<String>,
+    pub publics: Vec<(String, Segment, u16)>,
+    pub externals: Vec<String>,
+    pub code_size: u16,
+    pub data_size: u16,
+}
+
+struct PendingEquate {
+    name: String,
+    column: usize,
+    operand: Operand,
+    line: usize,
+    location: i64,
+    segment: Segment,
+}
+
+struct Frame {
+    lines: Vec<InputLine>,
+    pos: usize,
+    is_macro: bool,
+}
+
+struct Conditional {
+    parent_active: bool,
+    condition: bool,
+    in_else: bool,
+    origin: usize,
+}
+
+enum BlockKind {
+    Macro(String, Vec<String>),
+    Rept(i64),
+}
+
+//A MACRO or REPT body being gathered up to its matching ENDM
+struct Block {
+    kind: BlockKind,
+    body: Vec<InputLine>,
+    depth: usize,
+    origin: usize,
+}
+
+struct Assembler {
+    symbols: HashMap<String, i64>,
+    bases: HashMap<String, Base>,
+    set_symbols: HashSet<String>,
+    equates: Vec<PendingEquate>,
+    lines: Vec<SourceLine>,
+    location: i64,
+    segment: Segment,
+    //location counters of the segments not currently selected, and how far each reached
+    counters: HashMap<Segment, i64>,
+    sizes: HashMap<Segment, i64>,
+    name: Option<String>,
+    publics: Vec<(String, usize, usize)>,
+    externals: Vec<String>,
+    errors: Vec<AssemblyError>,
+    ended: bool,
+    origins: Vec<Origin>,
+    files: Vec<(String, PathBuf)>,
+    frames: Vec<Frame>,
+    macros: HashMap<String, Macro>,
+    conditions: Vec<Conditional>,
+    block: Option<Block>,
+    next_local: usize,
+}
+
+//INCLUDE paths in a source string are taken relative to the working directory
+pub fn assemble(source: &str) -> Result<Assembly, Vec<AssemblyError>> {
+    let mut assembler = Assembler::new();
+    assembler.push_source(source, None, PathBuf::from("."));
+    assembler.run()
+}
+
+//INCLUDE paths are taken relative to the including file
+pub fn assemble_file(path: &Path) -> Result<Assembly, Vec<AssemblyError>> {
+    let source = fs::read_to_string(path).map_err(|error| vec!(AssemblyError {
+        file: Some(path.display().to_string()),
+        line: 0,
+        column: 0,
+        message: format!("unable to read file: {}", error),
+    }))?;
+    let mut assembler = Assembler::new();
+    let dir = path.parent().map_or_else(|| PathBuf::from("."), |dir| dir.to_path_buf());
+    assembler.push_source(&source, Some(path.display().to_string()), dir);
+    assembler.run()
+}
+
+impl Assembly {
+    //Lowest address that received a byte, which is where the binary image starts
+    pub fn origin(&self) -> Address {
+        self.lines.iter()
+            .filter(|line| !line.bytes.is_empty())
+            .filter_map(|line| line.addr)
+            .min()
+            .unwrap_or(0)
+    }
+
+    //Code or data in a relocatable segment, or any linkage, needs the linker before it can run
+    pub fn is_relocatable(&self) -> bool {
+        self.lines.iter().any(|line| line.segment != Segment::Absolute && !line.bytes.is_empty())
+            || !self.publics.is_empty() || !self.externals.is_empty()
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        image(&self.memory_map())
+    }
+
+    //Later lines win when two ORG'd regions overlap
+    fn memory_map(&self) -> BTreeMap<Address, u8> {
+        let mut memory = BTreeMap::new();
+        for line in &self.lines {
+            if let Some(addr) = line.addr {
+                for (offset, byte) in line.bytes.iter().enumerate() {
+                    memory.insert(addr.wrapping_add(offset as u16), *byte);
+                }
+            }
+        }
+        memory
+    }
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            symbols: HashMap::new(),
+            bases: HashMap::new(),
+            set_symbols: HashSet::new(),
+            equates: vec!(),
+            lines: vec!(),
+            location: 0,
+            segment: Segment::Absolute,
+            counters: HashMap::new(),
+            sizes: HashMap::new(),
+            name: None,
+            publics: vec!(),
+            externals: vec!(),
+            errors: vec!(),
+            ended: false,
+            origins: vec!(),
+            files: vec!(),
+            frames: vec!(),
+            macros: HashMap::new(),
+            conditions: vec!(),
+            block: None,
+            next_local: 0,
+        }
+    }
+
+    //Two passes: the first fixes the address of every line and label, the second
+    //evaluates operands and emits bytes now that forward references are known.
+    fn run(mut self) -> Result<Assembly, Vec<AssemblyError>> {
+        while let Some(line) = self.next_line() {
+            if let Err(error) = self.preprocess(line) {
+                self.errors.push(error);
+            }
+            if self.ended {
+                break;
+            }
+        }
+        if let Some(block) = self.block.take() {
+            self.errors.push(AssemblyError::new(block.origin, 1, "missing ENDM".to_string()));
+        }
+        if let Some(conditional) = self.conditions.pop() {
+            self.errors.push(AssemblyError::new(conditional.origin, 1, "missing ENDIF".to_string()));
+        }
+        self.resolve_equates();
+        let mut assembly = self.second_pass();
+        for line in &mut assembly.lines {
+            line.number = self.origins[line.number].line;
+        }
+        if self.errors.is_empty() {
+            return Ok(assembly);
+        }
+        let mut errors: Vec<AssemblyError> = self.errors.drain(..).collect();
+        errors.sort_by_key(|error| (error.line, error.column));
+        for error in &mut errors {
+            let origin = &self.origins[error.line];
+            error.file = origin.file.map(|file| self.files[file].0.clone());
+            error.line = origin.line;
+        }
+        Err(errors)
+    }
+
+    fn push_source(&mut self, source: &str, name: Option<String>, dir: PathBuf) {
+        let file = name.map(|name| {
+            self.files.push((name, dir.clone()));
+            self.files.len() - 1
+        });
+        let mut lines = vec!();
+        for (index, text) in source.lines().enumerate() {
+            self.origins.push(Origin { file, line: index + 1 });
+            lines.push(InputLine { text: text.to_string(), origin: self.origins.len() - 1, expanded: false });
+        }
+        self.frames.push(Frame { lines, pos: 0, is_macro: false });
+    }
+
+    fn next_line(&mut self) -> Option<InputLine> {
+        loop {
+            let frame = self.frames.last_mut()?;
+            if frame.pos < frame.lines.len() {
+                frame.pos += 1;
+                return Some(frame.lines[frame.pos - 1].clone());
+            }
+            self.frames.pop();
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.conditions.last().map_or(true, |conditional|
+            conditional.parent_active && conditional.condition != conditional.in_else)
+    }
+
+    fn list_only(&mut self, line: &InputLine) {
+        self.lines.push(SourceLine {
+            number: line.origin,
+            text: line.text.clone(),
+            expanded: line.expanded,
+            location: self.location,
+            segment: self.segment,
+            set_value: None,
+            statement: Statement { label: None, operation: None, operands: vec!() },
+        });
+    }
+
+    //Handles the directives that shape which lines get assembled (macros, repeats,
+    //conditionals and includes) before handing ordinary lines to the first pass.
+    fn preprocess(&mut self, line: InputLine) -> Result<(), AssemblyError> {
+        let number = line.origin;
+        let parsed = parse_statement(number, &line.text, &self.macros);
+        let operation = match parsed {
+            Ok(Statement { operation: Some((ref name, _)), .. }) => Some(name.clone()),
+            _ => None,
+        };
+
+        if let Some(mut block) = self.block.take() {
+            match operation.as_deref() {
+                Some("MACRO") | Some("REPT") => block.depth += 1,
+                Some("ENDM") if block.depth == 0 => {
+                    self.list_only(&line);
+                    return self.finish_block(block, number);
+                },
+                Some("ENDM") => block.depth -= 1,
+                _ => {},
+            }
+            block.body.push(line.clone());
+            self.block = Some(block);
+            self.list_only(&line);
+            return Ok(());
+        }
+
+        match operation.as_deref() {
+            Some("IF") => {
+                let parent_active = self.is_active();
+                let mut condition = false;
+                if parent_active {
+                    let statement = parsed?;
+                    let column = statement.operation.as_ref().map_or(1, |operation| operation.1);
+                    let operand = single_operand(number, column, "IF", &statement.operands)?;
+                    condition = self.eval_now(number, self.location, operand)? != 0;
+                }
+                self.conditions.push(Conditional { parent_active, condition, in_else: false, origin: number });
+                self.list_only(&line);
+                return Ok(());
+            },
+            Some("ELSE") | Some("ENDIF") => {
+                self.list_only(&line);
+                let column = parsed.as_ref().ok()
+                    .and_then(|statement| statement.operation.as_ref().map(|operation| operation.1))
+                    .unwrap_or(1);
+                let is_else = operation.as_deref() == Some("ELSE");
+                return match self.conditions.last_mut() {
+                    Some(ref conditional) if is_else && conditional.in_else =>
+                        Err(AssemblyError::new(number, column, "ELSE repeated in the same IF".to_string())),
+                    Some(conditional) if is_else => {
+                        conditional.in_else = true;
+                        Ok(())
+                    },
+                    Some(_) => {
+                        self.conditions.pop();
+                        Ok(())
+                    },
+                    None => Err(AssemblyError::new(number, column, format!("{} without IF", operation.unwrap_or_default()))),
+                };
+            },
+            _ => {},
+        }
+        if !self.is_active() {
+            self.list_only(&line);
+            return Ok(());
+        }
+
+        let statement = parsed?;
+        let (name, column) = match statement.operation {
+            Some((ref name, column)) => (name.clone(), column),
+            None => return self.first_pass(&line, statement),
+        };
+        match name.as_str() {
+            "MACRO" => {
+                let macro_name = match statement.label {
+                    Some((ref label, _)) => label.clone(),
+                    None => return Err(AssemblyError::new(number, column, "MACRO needs a name".to_string())),
+                };
+                let mut params = vec!();
+                for operand in &statement.operands {
+                    match operand.tokens.as_slice() {
+                        [Tok { token: Token::Ident(ref param), .. }] => params.push(param.clone()),
+                        _ => return Err(AssemblyError::new(number, operand.column, "expected a parameter name".to_string())),
+                    }
+                }
+                self.list_only(&line);
+                self.block = Some(Block { kind: BlockKind::Macro(macro_name, params), body: vec!(), depth: 0, origin: number });
+                Ok(())
+            },
+            "REPT" => {
+                let operand = single_operand(number, column, &name, &statement.operands)?;
+                let count = self.eval_now(number, self.location, operand)?;
+                self.list_only(&line);
+                //the body is still collected, so its ENDM doesn't report another error
+                let (count, result) = if count > MAX_REPEATS {
+                    (0, Err(AssemblyError::new(number, operand.column, "REPT count too large".to_string())))
+                } else {
+                    (count, Ok(()))
+                };
+                self.block = Some(Block { kind: BlockKind::Rept(count), body: vec!(), depth: 0, origin: number });
+                result
+            },
+            "ENDM" => Err(AssemblyError::new(number, column, "ENDM without MACRO or REPT".to_string())),
+            "LOCAL" => Err(AssemblyError::new(number, column, "LOCAL outside a macro".to_string())),
+            "EXITM" => {
+                self.list_only(&line);
+                match self.frames.iter().rposition(|frame| frame.is_macro) {
+                    Some(index) => {
+                        self.frames.truncate(index);
+                        Ok(())
+                    },
+                    None => Err(AssemblyError::new(number, column, "EXITM outside a macro".to_string())),
+                }
+            },
+            INCLUDE => {
+                self.list_only(&line);
+                let path = raw_operand_text(&line.text, column, &name);
+                let path = path.trim_matches(|c| c == '\'' || c == '"');
+                self.include(path, number, column)
+            },
+            _ if self.macros.contains_key(&name) => {
+                if self.frames.len() >= MAX_NESTING {
+                    return Err(AssemblyError::new(number, column, format!("macro {} nests too deeply", name)));
+                }
+                //the call line itself only defines its label
+                let label = Statement { label: statement.label, operation: None, operands: vec!() };
+                self.first_pass(&line, label)?;
+                let args = macros::split_arguments(&raw_operand_text(&line.text, column, &name));
+                let lines = self.macros[&name].expand(&args, number, &mut self.next_local);
+                self.frames.push(Frame { lines, pos: 0, is_macro: true });
+                Ok(())
+            },
+            _ => self.first_pass(&line, statement),
+        }
+    }
+
+    fn finish_block(&mut self, block: Block, number: usize) -> Result<(), AssemblyError> {
+        match block.kind {
+            BlockKind::Macro(name, params) => {
+                self.macros.insert(name, Macro { params, body: block.body });
+            },
+            BlockKind::Rept(count) => {
+                if self.frames.len() >= MAX_NESTING {
+                    return Err(AssemblyError::new(number, 1, "REPT nests too deeply".to_string()));
+                }
+                let mut lines = vec!();
+                for _ in 0..count.max(0) {
+                    lines.extend(block.body.iter().map(|line| InputLine { expanded: true, ..line.clone() }));
+                }
+                self.frames.push(Frame { lines, pos: 0, is_macro: false });
+            },
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &str, number: usize, column: usize) -> Result<(), AssemblyError> {
+        if self.frames.len() >= MAX_NESTING {
+            return Err(AssemblyError::new(number, column, format!("INCLUDE of {} nests too deeply", path)));
+        }
+        let dir = self.origins[number].file.map_or_else(|| PathBuf::from("."), |file| self.files[file].1.clone());
+        let full_path = dir.join(path);
+        let source = fs::read_to_string(&full_path).map_err(|error|
+            AssemblyError::new(number, column, format!("unable to include {}: {}", full_path.display(), error)))?;
+        let include_dir = full_path.parent().map_or_else(|| PathBuf::from("."), |dir| dir.to_path_buf());
+        self.push_source(&source, Some(full_path.display().to_string()), include_dir);
+        Ok(())
+    }
+
+    fn first_pass(&mut self, line: &InputLine, statement: Statement) -> Result<(), AssemblyError> {
+        let number = line.origin;
+        let start = self.location;
+        let segment = self.segment;
+        let source_line = |statement, set_value| SourceLine {
+            number,
+            text: line.text.clone(),
+            expanded: line.expanded,
+            location: start,
+            segment,
+            set_value,
+            statement,
+        };
+        let (operation, column) = match statement.operation {
+            Some((ref operation, column)) => (operation.clone(), column),
+            None => {
+                if let Some((ref name, column)) = statement.label {
+                    self.define(name, start, Base::Segment(segment), number, column)?;
+                }
+                self.lines.push(source_line(statement, None));
+                return Ok(());
+            },
+        };
+        if operation == "EQU" {
+            let (name, name_column) = match statement.label {
+                Some((ref name, name_column)) => (name.clone(), name_column),
+                None => return Err(AssemblyError::new(number, column, "EQU needs a name".to_string())),
+            };
+            let operand = single_operand(number, column, &operation, &statement.operands)?;
+            let operand = Operand { tokens: operand.tokens.clone(), column: operand.column };
+            self.lines.push(source_line(statement, None));
+            //an EQU may refer to labels further down, so retry those once every label is known
+            self.equates.push(PendingEquate { name, column: name_column, operand, line: number, location: start, segment });
+            self.resolve_equates_quietly();
+            return Ok(());
+        }
+        if operation == "SET" {
+            let (name, name_column) = match statement.label {
+                Some((ref name, name_column)) => (name.clone(), name_column),
+                None => return Err(AssemblyError::new(number, column, "SET needs a name".to_string())),
+            };
+            if self.symbols.contains_key(&name) && !self.set_symbols.contains(&name) {
+                return Err(AssemblyError::new(number, name_column, format!("symbol {} is already defined", name)));
+            }
+            let operand = single_operand(number, column, &operation, &statement.operands)?;
+            let value = self.eval_now(number, start, operand)?;
+            let base = self.base_now(number, start, operand)?;
+            self.set_symbols.insert(name.clone());
+            self.symbols.insert(name.clone(), value);
+            self.bases.insert(name, base.clone());
+            self.lines.push(source_line(statement, Some((value, base))));
+            return Ok(());
+        }
+        if let Some((ref name, label_column)) = statement.label {
+            self.define(name, start, Base::Segment(segment), number, label_column)?;
+        }
+        match operation.as_str() {
+            "ASEG" | "CSEG" | "DSEG" => {
+                let selected = match operation.as_str() {
+                    "ASEG" => Segment::Absolute,
+                    "CSEG" => Segment::Code,
+                    _ => Segment::Data,
+                };
+                self.counters.insert(segment, start);
+                self.location = self.counters.get(&selected).cloned().unwrap_or(0);
+                self.segment = selected;
+            },
+            "PUBLIC" => for operand in &statement.operands {
+                let name = linkage_name(number, operand)?;
+                self.publics.push((name, number, operand.column));
+            },
+            "EXTRN" => for operand in &statement.operands {
+                let name = linkage_name(number, operand)?;
+                self.define(&name, 0, Base::External(name.clone()), number, operand.column)?;
+                self.externals.push(name);
+            },
+            "NAME" => {
+                let operand = single_operand(number, column, &operation, &statement.operands)?;
+                self.name = match operand.tokens.as_slice() {
+                    [Tok { token: Token::Ident(ref name), .. }] => Some(name.clone()),
+                    [Tok { token: Token::Str(ref name), .. }] |
+                    [Tok { token: Token::LParen, .. }, Tok { token: Token::Str(ref name), .. }, Tok { token: Token::RParen, .. }] =>
+                        Some(name.to_ascii_uppercase()),
+                    _ => return Err(AssemblyError::new(number, operand.column, "expected a module name".to_string())),
+                };
+            },
+            "ORG" => {
+                let operand = single_operand(number, column, &operation, &statement.operands)?;
+                let origin = self.eval_now(number, start, operand)?;
+                if !(0..=0xffff).contains(&origin) {
+                    return Err(AssemblyError::new(number, operand.column, format!("origin {} is outside memory", origin)));
+                }
+                self.location = origin;
+            },
+            "DS" => {
+                let operand = single_operand(number, column, &operation, &statement.operands)?;
+                let size = self.eval_now(number, start, operand)?;
+                if size < 0 {
+                    return Err(AssemblyError::new(number, operand.column, "DS needs a size of zero or more".to_string()));
+                }
+                self.location += size;
+            },
+            "DB" => {
+                let size: usize = statement.operands.iter().map(|operand| match operand.tokens.as_slice() {
+                    [Tok { token: Token::Str(ref text), .. }] => text.len(),
+                    _ => 1,
+                }).sum();
+                self.location += size as i64;
+            },
+            "DW" => self.location += 2 * statement.operands.len() as i64,
+            "END" => self.ended = true,
+            _ => match mnemonics::lookup(&operation) {
+                Some(shape) => self.location += shape.size() as i64,
+                None => return Err(AssemblyError::new(number, column, format!("unknown instruction {}", operation))),
+            },
+        }
+        self.lines.push(source_line(statement, None));
+        let size = self.sizes.entry(self.segment).or_insert(0);
+        *size = (*size).max(self.location);
+        if self.location > 0x10000 {
+            return Err(AssemblyError::new(number, column, "code runs past the end of memory".to_string()));
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: i64, base: Base, line: usize, column: usize) -> Result<(), AssemblyError> {
+        if self.symbols.contains_key(name) {
+            return Err(AssemblyError::new(line, column, format!("symbol {} is already defined", name)));
+        }
+        self.symbols.insert(name.to_string(), value);
+        self.bases.insert(name.to_string(), base);
+        Ok(())
+    }
+
+    fn context(&self, line: usize, location: i64, segment: Segment) -> Context<'_> {
+        Context { symbols: &self.symbols, bases: &self.bases, location, segment, line }
+    }
+
+    //ORG and DS decide where later lines land, so their operands must already be known
+    fn eval_now(&self, line: usize, location: i64, operand: &Operand) -> Result<i64, AssemblyError> {
+        mnemonics::value(operand, &self.context(line, location, self.segment))
+    }
+
+    fn base_now(&self, line: usize, location: i64, operand: &Operand) -> Result<Base, AssemblyError> {
+        mnemonics::base(operand, &self.context(line, location, self.segment))
+    }
+
+    fn resolve_equates_quietly(&mut self) {
+        loop {
+            let mut progress = false;
+            let pending: Vec<PendingEquate> = self.equates.drain(..).collect();
+            for equate in pending {
+                let context = self.context(equate.line, equate.location, equate.segment);
+                let result = mnemonics::value(&equate.operand, &context)
+                    .and_then(|value| Ok((value, mnemonics::base(&equate.operand, &context)?)));
+                match result {
+                    Ok((value, base)) => {
+                        if let Err(error) = self.define(&equate.name, value, base, equate.line, equate.column) {
+                            self.errors.push(error);
+                        }
+                        progress = true;
+                    },
+                    Err(_) => self.equates.push(equate),
+                }
+            }
+            if !progress {
+                return;
+            }
+        }
+    }
+
+    fn resolve_equates(&mut self) {
+        self.resolve_equates_quietly();
+        let pending: Vec<PendingEquate> = self.equates.drain(..).collect();
+        for equate in pending {
+            let context = self.context(equate.line, equate.location, equate.segment);
+            if let Err(error) = mnemonics::value(&equate.operand, &context) {
+                self.errors.push(error);
+            }
+        }
+    }
+
+    fn second_pass(&mut self) -> Assembly {
+        let mut listed = vec!();
+        let mut start = None;
+        let mut start_segment = Segment::Absolute;
+        for line in &self.lines {
+            //a SET symbol takes each of its values in turn, as it did during the first pass
+            if let (Some((value, ref base)), Some((ref name, _))) = (line.set_value.as_ref(), &line.statement.label) {
+                self.symbols.insert(name.clone(), *value);
+                self.bases.insert(name.clone(), base.clone());
+            }
+            let context = Context {
+                symbols: &self.symbols,
+                bases: &self.bases,
+                location: line.location,
+                segment: line.segment,
+                line: line.number,
+            };
+            let mut listed_line = ListedLine {
+                number: line.number,
+                text: line.text.clone(),
+                expanded: line.expanded,
+                addr: None,
+                segment: line.segment,
+                bytes: vec!(),
+                relocations: vec!(),
+                value: None,
+            };
+            match emit(&line.statement, &context) {
+                Ok(Emitted::Bytes(bytes, relocations)) => {
+                    listed_line.addr = Some(line.location as Address);
+                    listed_line.bytes = bytes;
+                    listed_line.relocations = relocations;
+                },
+                Ok(Emitted::Value(value)) => listed_line.value = Some(value),
+                Ok(Emitted::Start(addr, Base::Segment(segment))) => {
+                    start = addr;
+                    start_segment = segment;
+                },
+                Ok(Emitted::Start(_, Base::External(name))) => self.errors.push(AssemblyError::new(line.number,
+                    line.statement.operands[0].column, format!("the start address can't be the external {}", name))),
+                Ok(Emitted::Nothing) => {},
+                Err(error) => self.errors.push(error),
+            }
+            listed.push(listed_line);
+        }
+        let mut publics = vec!();
+        for (name, line, column) in &self.publics {
+            match (self.symbols.get(name), self.bases.get(name)) {
+                (Some(value), Some(Base::Segment(segment))) => publics.push((name.clone(), *segment, *value as u16)),
+                (Some(_), _) => self.errors.push(AssemblyError::new(*line, *column, format!("{} is external, so it can't be public", name))),
+                (None, _) => self.errors.push(AssemblyError::new(*line, *column, format!("public symbol {} is never defined", name))),
+            }
+        }
+        let symbols = self.symbols.iter().map(|(name, value)| (name.clone(), *value as u16)).collect();
+        let size = |segment| self.sizes.get(&segment).cloned().unwrap_or(0) as u16;
+        Assembly {
+            lines: listed,
+            symbols,
+            start,
+            start_segment,
+            name: self.name.clone(),
+            publics,
+            externals: self.externals.clone(),
+            code_size: size(Segment::Code),
+            data_size: size(Segment::Data),
+        }
+    }
+}
+
+enum Emitted {
+    Nothing,
+    Bytes(Vec<u8>, Vec<(usize, Base)>),
+    Value(u16),
+    Start(Option<Address>, Base),
+}
+
+fn emit(statement: &Statement, context: &Context) -> Result<Emitted, AssemblyError> {
+    let (operation, column) = match statement.operation {
+        Some((ref operation, column)) => (operation.as_str(), column),
+        None => return Ok(Emitted::Nothing),
+    };
+    let operands = &statement.operands;
+    let emitted = match operation {
+        "EQU" => {
+            let name = &statement.label.as_ref().expect("EQU lines always carry a name").0;
+            Emitted::Value(context.symbols.get(name).map_or(0, |value| *value as u16))
+        },
+        "SET" => Emitted::Value(mnemonics::value(&operands[0], context)? as u16),
+        "ORG" | "DS" => Emitted::Bytes(vec!(), vec!()),
+        "ASEG" | "CSEG" | "DSEG" | "PUBLIC" | "EXTRN" | "NAME" => Emitted::Nothing,
+        "END" => match operands.first() {
+            Some(operand) => Emitted::Start(Some(mnemonics::word_value(operand, context)?), mnemonics::base(operand, context)?),
+            None => Emitted::Start(None, ABSOLUTE),
+        },
+        "DB" => {
+            let mut bytes = vec!();
+            for operand in operands {
+                match operand.tokens.as_slice() {
+                    [Tok { token: Token::Str(ref text), .. }] if text.len() != 1 =>
+                        bytes.extend(text.bytes()),
+                    _ => bytes.push(mnemonics::byte_value(operand, context)?),
+                }
+            }
+            Emitted::Bytes(bytes, vec!())
+        },
+        "DW" => {
+            let mut bytes = vec!();
+            let mut relocations = vec!();
+            for operand in operands {
+                let word = mnemonics::word_value(operand, context)?;
+                let base = mnemonics::base(operand, context)?;
+                if base != ABSOLUTE {
+                    relocations.push((bytes.len(), base));
+                }
+                bytes.push(word as u8);
+                bytes.push((word >> 8) as u8);
+            }
+            Emitted::Bytes(bytes, relocations)
+        },
+        _ => {
+            let bytes = mnemonics::build(operation, column, operands, context)?.encode()
+                .map_err(|error| AssemblyError::new(context.line, column, error.to_string()))?;
+            //the 16-bit operand always follows the opcode
+            let relocations = match mnemonics::word_operand(operation, operands) {
+                Some(operand) => match mnemonics::base(operand, context)? {
+                    ABSOLUTE => vec!(),
+                    base => vec!((1, base)),
+                },
+                None => vec!(),
+            };
+            Emitted::Bytes(bytes, relocations)
+        },
+    };
+    Ok(emitted)
+}
+
+//PUBLIC and EXTRN names have to fit the REL format's short symbol names
+fn linkage_name(line: usize, operand: &Operand) -> Result<String, AssemblyError> {
+    match operand.tokens.as_slice() {
+        [Tok { token: Token::Ident(ref name), .. }] if name.len() <= MAX_NAME_LENGTH => Ok(name.clone()),
+        [Tok { token: Token::Ident(ref name), .. }] => Err(AssemblyError::new(line, operand.column,
+            format!("{} is longer than the {} characters an object file can hold", name, MAX_NAME_LENGTH))),
+        _ => Err(AssemblyError::new(line, operand.column, "expected a symbol name".to_string())),
+    }
+}
+
+fn single_operand<'a>(line: usize, column: usize, operation: &str, operands: &'a [Operand]) -> Result<&'a Operand, AssemblyError> {
+    match operands {
+        [operand] => Ok(operand),
+        _ => Err(AssemblyError::new(line, column, format!("{} takes 1 operand, found {}", operation, operands.len()))),
+    }
+}
+
+fn is_operation(name: &str, macros: &HashMap<String, Macro>) -> bool {
+    DIRECTIVES.contains(&name) || name == INCLUDE || mnemonics::lookup(name).is_some() || macros.contains_key(name)
+}
+
+//Everything after the operation up to the comment, untokenized, for INCLUDE paths and macro arguments
+fn raw_operand_text(text: &str, column: usize, operation: &str) -> String {
+    let rest: String = text.chars().skip(column - 1 + operation.chars().count()).collect();
+    let mut quote = None;
+    let mut end = rest.len();
+    for (index, c) in rest.char_indices() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {},
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == ';' => {
+                end = index;
+                break;
+            },
+            None => {},
+        }
+    }
+    rest[..end].trim().to_string()
+}
+
+//Intel syntax: [label[:]] [operation [operand[,operand]...]] [; comment]
+//A label without a colon is only recognised in the first column, or in front of EQU, SET or MACRO.
+fn parse_statement(line: usize, text: &str, macros: &HashMap<String, Macro>) -> Result<Statement, AssemblyError> {
+    let tokens = lexer::tokenize(line, text)?;
+    let mut pos = 0;
+    let mut label = None;
+    if let Some(Tok { token: Token::Ident(ref name), column }) = tokens.first() {
+        let next = tokens.get(1).map(|tok| &tok.token);
+        let labels_next = match next {
+            Some(Token::Colon) => true,
+            Some(Token::Ident(next_name)) => next_name == "EQU" || next_name == "SET" || next_name == "MACRO",
+            _ => false,
+        };
+        if labels_next || (*column == 1 && !is_operation(name, macros)) {
+            label = Some((name.clone(), *column));
+            pos = if next == Some(&Token::Colon) { 2 } else { 1 };
+        }
+    }
+    let operation = match tokens.get(pos) {
+        Some(Tok { token: Token::Ident(ref name), column }) => Some((name.clone(), *column)),
+        Some(tok) => return Err(AssemblyError::new(line, tok.column,
+            format!("expected an instruction or directive, found {}", expression::describe(&tok.token)))),
+        None => None,
+    };
+    let mut operands = vec!();
+    if operation.is_some() {
+        let rest = &tokens[pos + 1..];
+        if !rest.is_empty() {
+            let mut current: Vec<Tok> = vec!();
+            let mut depth = 0;
+            for tok in rest {
+                match tok.token {
+                    Token::LParen => depth += 1,
+                    Token::RParen => depth -= 1,
+                    Token::Comma if depth == 0 => {
+                        if current.is_empty() {
+                            return Err(AssemblyError::new(line, tok.column, "missing operand".to_string()));
+                        }
+                        let column = current[0].column;
+                        operands.push(Operand { tokens: current, column });
+                        current = vec!();
+                        continue;
+                    },
+                    _ => {},
+                }
+                current.push(tok.clone());
+            }
+            if current.is_empty() {
+                let column = rest.last().map_or(1, |tok| tok.column + 1);
+                return Err(AssemblyError::new(line, column, "missing operand".to_string()));
+            }
+            let column = current[0].column;
+            operands.push(Operand { tokens: current, column });
+        }
+    }
+    Ok(Statement { label, operation, operands })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use testing::TempDir;
+    use super::assemble;
+
+    #[test]
+    fn assembles_forward_references_and_directives() {
+        let source = "\
+BDOS    EQU     LAST+2          ; resolved once LAST is known
+        ORG     100H
+START:  LXI     H,MSG
+        MVI     C,LOW (BDOS SHR 1)
+        JNZ     START
+MSG:    DB      'Hi',0DH,'$'
+        DW      START, $
+LAST:   DS      2
+        END     START
+";
+        let assembly = assemble(source).unwrap_or_else(|errors| panic!("{}", errors[0]));
+        assert_eq!(assembly.origin(), 0x100);
+        assert_eq!(assembly.start, Some(0x100));
+        assert_eq!(assembly.symbols["BDOS"], 0x112);
+        assert_eq!(assembly.to_binary(), vec!(
+            0x21, 0x08, 0x01,
+            0x0e, 0x89,
+            0xc2, 0x00, 0x01,
+            b'H', b'i', 0x0d, b'$',
+            0x00, 0x01, 0x0c, 0x01,
+        ));
+    }
+
+    #[test]
+    fn expands_macros_repeats_conditionals_and_includes() {
+        let temp = TempDir::new();
+        let include = temp.0.join("ports.inc");
+        fs::write(&include, "PORT    EQU     10H\n").unwrap();
+        let source = format!("\
+        INCLUDE {}
+WAIT    MACRO   MASK
+        LOCAL   LOOP
+LOOP:   IN      PORT
+        ANI     MASK
+        JZ      LOOP
+        ENDM
+COUNT   SET     0
+        REPT    3
+        DB      COUNT
+COUNT   SET     COUNT+1
+        ENDM
+        IF      COUNT EQ 3
+        WAIT    <1 OR 2>
+        ELSE
+        NOP
+        ENDIF
+        WAIT    80H
+", include.display());
+        let assembly = assemble(&source).unwrap_or_else(|errors| panic!("{}", errors[0]));
+        assert_eq!(assembly.to_binary(), vec!(
+            0x00, 0x01, 0x02,
+            0xdb, 0x10, 0xe6, 0x03, 0xca, 0x03, 0x00,
+            0xdb, 0x10, 0xe6, 0x80, 0xca, 0x0a, 0x00,
+        ));
+    }
+
+    #[test]
+    fn reports_line_and_column_of_errors() {
+        let source = "\tMOV A,B\n\tMVI A,NOWHERE\n\tLDAX H\n\tFOO 1\n\tADI 300\n\tMOV M,M";
+        let errors = assemble(source).err().unwrap();
+        let located: Vec<(usize, usize)> = errors.iter().map(|error| (error.line, error.column)).collect();
+        assert_eq!(located, vec!((2, 8), (3, 7), (4, 2), (5, 6), (6, 6)));
+        assert_eq!(errors[0].message, "undefined symbol NOWHERE");
+        assert_eq!(errors[4].message, "MOV M,M is not a valid instruction");
+    }
+
+    #[test]
+    fn reports_values_that_overflow() {
+        let source = "\
+MIN     EQU     -7FFFFFFFFFFFFFFFH-1
+        DW      7FFFFFFFFFFFFFFFH+1
+        DW      MIN-1
+        DW      MIN*2
+        DW      MIN/-1
+        DW      MIN MOD -1
+        DW      -MIN
+";
+        let errors = assemble(source).err().unwrap();
+        let located: Vec<(usize, usize)> = errors.iter().map(|error| (error.line, error.column)).collect();
+        assert_eq!(located, vec!((2, 34), (3, 20), (4, 20), (5, 20), (6, 21), (7, 18)));
+        assert!(errors.iter().all(|error| error.message == "value overflows"));
+    }
+
+    #[test]
+    fn rejects_repeat_counts_past_the_size_of_memory() {
+        let errors = assemble("\tREPT 100000000\n\tNOP\n\tENDM\n").err().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].line, errors[0].column), (1, 7));
+        assert_eq!(errors[0].message, "REPT count too large");
+        let assembly = assemble("\tREPT 10000H\n\tNOP\n\tENDM\n").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        assert_eq!(assembly.to_binary().len(), 0x10000);
+    }
+}
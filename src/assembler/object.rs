@@ -0,0 +1,454 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Read, Write};
+
+use super::Assembly;
+use super::expression::Base;
+
+//Microsoft REL address types
+const ABSOLUTE: u32 = 0b00;
+const PROGRAM_RELATIVE: u32 = 0b01;
+const DATA_RELATIVE: u32 = 0b10;
+const COMMON_RELATIVE: u32 = 0b11;
+
+const ENTRY_SYMBOL: u32 = 0;
+const PROGRAM_NAME: u32 = 2;
+const CHAIN_EXTERNAL: u32 = 6;
+const DEFINE_ENTRY_POINT: u32 = 7;
+const EXTERNAL_MINUS_OFFSET: u32 = 8;
+const EXTERNAL_PLUS_OFFSET: u32 = 9;
+const DATA_SIZE: u32 = 10;
+const SET_LOCATION: u32 = 11;
+const PROGRAM_SIZE: u32 = 13;
+const END_MODULE: u32 = 14;
+const END_FILE: u32 = 15;
+
+pub const MAX_NAME_LENGTH: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Segment {
+    Absolute,
+    Code,
+    Data,
+}
+
+//A run of bytes in one segment; each relocation marks a little-endian word that
+//still needs the segment's load address added to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub segment: Segment,
+    pub offset: u16,
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<(usize, Segment)>,
+}
+
+//A word that receives the value of an external symbol plus the addend
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternalRef {
+    pub name: String,
+    pub segment: Segment,
+    pub offset: u16,
+    pub addend: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectModule {
+    pub name: Option<String>,
+    pub code_size: u16,
+    pub data_size: u16,
+    pub chunks: Vec<Chunk>,
+    pub publics: Vec<(String, Segment, u16)>,
+    pub externals: Vec<ExternalRef>,
+    pub start: Option<(Segment, u16)>,
+}
+
+impl ObjectModule {
+    //Writes the module in Microsoft REL format. References to each external are chained
+    //through the words they occupy, with the head of the chain given at the end.
+    pub fn write_rel<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut bits = BitWriter { bytes: vec!(), current: 0, count: 0 };
+        if let Some(ref name) = self.name {
+            bits.special(PROGRAM_NAME, None, Some(name));
+        }
+        for (name, _, _) in &self.publics {
+            bits.special(ENTRY_SYMBOL, None, Some(name));
+        }
+        if self.data_size > 0 {
+            bits.special(DATA_SIZE, Some((ABSOLUTE, self.data_size)), None);
+        }
+        bits.special(PROGRAM_SIZE, Some((PROGRAM_RELATIVE, self.code_size)), None);
+
+        let references: HashMap<(Segment, u16), &ExternalRef> = self.externals.iter()
+            .map(|external| ((external.segment, external.offset), external))
+            .collect();
+        let mut chain_heads: BTreeMap<&str, (Segment, u16)> = BTreeMap::new();
+        for chunk in &self.chunks {
+            bits.special(SET_LOCATION, Some((address_type(chunk.segment), chunk.offset)), None);
+            let relocations: HashMap<usize, Segment> = chunk.relocations.iter().cloned().collect();
+            let mut index = 0;
+            while index < chunk.bytes.len() {
+                let offset = chunk.offset.wrapping_add(index as u16);
+                if let Some(external) = references.get(&(chunk.segment, offset)) {
+                    if external.addend > 0 {
+                        bits.special(EXTERNAL_PLUS_OFFSET, Some((ABSOLUTE, external.addend as u16)), None);
+                    } else if external.addend < 0 {
+                        bits.special(EXTERNAL_MINUS_OFFSET, Some((ABSOLUTE, (-external.addend) as u16)), None);
+                    }
+                    //each reference holds the previous one; absolute zero ends the chain
+                    match chain_heads.insert(&external.name, (chunk.segment, offset)) {
+                        Some((segment, previous)) if segment != Segment::Absolute =>
+                            bits.word(address_type(segment), previous),
+                        Some((_, previous)) => {
+                            bits.byte(previous as u8);
+                            bits.byte((previous >> 8) as u8);
+                        },
+                        None => {
+                            bits.byte(0);
+                            bits.byte(0);
+                        },
+                    }
+                    index += 2;
+                } else if let Some(&segment) = relocations.get(&index) {
+                    let word = chunk.bytes[index] as u16 | (chunk.bytes[index + 1] as u16) << 8;
+                    bits.word(address_type(segment), word);
+                    index += 2;
+                } else {
+                    bits.byte(chunk.bytes[index]);
+                    index += 1;
+                }
+            }
+        }
+        for (name, (segment, offset)) in chain_heads {
+            bits.special(CHAIN_EXTERNAL, Some((address_type(segment), offset)), Some(name));
+        }
+        for (name, segment, value) in &self.publics {
+            bits.special(DEFINE_ENTRY_POINT, Some((address_type(*segment), *value)), Some(name));
+        }
+        let start = self.start.map_or((ABSOLUTE, 0), |(segment, addr)| (address_type(segment), addr));
+        bits.special(END_MODULE, Some(start), None);
+        bits.align();
+        bits.special(END_FILE, None, None);
+        bits.align();
+        out.write_all(&bits.bytes)
+    }
+
+    //Reads every module in a REL file, undoing the external chains back into references
+    pub fn read_rel<R: Read>(input: &mut R) -> io::Result<Vec<ObjectModule>> {
+        let mut bytes = vec!();
+        input.read_to_end(&mut bytes)?;
+        let mut bits = BitReader { bytes: &bytes, pos: 0 };
+        let mut modules = vec!();
+        loop {
+            let mut module = ModuleReader::new();
+            if !module.read(&mut bits)? {
+                return Ok(modules);
+            }
+            modules.push(module.finish()?);
+            bits.align();
+        }
+    }
+}
+
+impl Assembly {
+    pub fn to_object(&self) -> ObjectModule {
+        let mut chunks: Vec<Chunk> = vec!();
+        let mut externals = vec!();
+        for line in &self.lines {
+            let addr = match line.addr {
+                Some(addr) if !line.bytes.is_empty() => addr,
+                _ => continue,
+            };
+            let extends = match chunks.last() {
+                Some(chunk) => chunk.segment == line.segment && chunk.offset as usize + chunk.bytes.len() == addr as usize,
+                None => false,
+            };
+            if !extends {
+                chunks.push(Chunk { segment: line.segment, offset: addr, bytes: vec!(), relocations: vec!() });
+            }
+            let chunk = chunks.last_mut().expect("a chunk was just pushed");
+            for (index, base) in &line.relocations {
+                match base {
+                    Base::Segment(segment) => chunk.relocations.push((chunk.bytes.len() + index, *segment)),
+                    Base::External(name) => {
+                        //an external's own value counts as zero, so the word holds just the addend
+                        let word = line.bytes[*index] as u16 | (line.bytes[index + 1] as u16) << 8;
+                        externals.push(ExternalRef {
+                            name: name.clone(),
+                            segment: line.segment,
+                            offset: addr.wrapping_add(*index as u16),
+                            addend: word as i16 as i32,
+                        });
+                    },
+                }
+            }
+            chunk.bytes.extend(&line.bytes);
+        }
+        externals.sort_by_key(|external| (external.segment, external.offset));
+        ObjectModule {
+            name: self.name.clone(),
+            code_size: self.code_size,
+            data_size: self.data_size,
+            chunks,
+            publics: self.publics.clone(),
+            externals,
+            start: self.start.map(|addr| (self.start_segment, addr)),
+        }
+    }
+}
+
+fn address_type(segment: Segment) -> u32 {
+    match segment {
+        Segment::Absolute => ABSOLUTE,
+        Segment::Code => PROGRAM_RELATIVE,
+        Segment::Data => DATA_RELATIVE,
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn segment_of(address_type: u32) -> io::Result<Segment> {
+    match address_type {
+        ABSOLUTE => Ok(Segment::Absolute),
+        PROGRAM_RELATIVE => Ok(Segment::Code),
+        DATA_RELATIVE => Ok(Segment::Data),
+        COMMON_RELATIVE => Err(invalid("COMMON blocks are not supported".to_string())),
+        _ => unreachable!(),
+    }
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    count: u8,
+}
+
+impl BitWriter {
+    fn bits(&mut self, value: u32, width: u32) {
+        for shift in (0..width).rev() {
+            self.current = self.current << 1 | ((value >> shift) & 1) as u8;
+            self.count += 1;
+            if self.count == 8 {
+                self.bytes.push(self.current);
+                self.current = 0;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn align(&mut self) {
+        while self.count != 0 {
+            self.bits(0, 1);
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.bits(0, 1);
+        self.bits(value as u32, 8);
+    }
+
+    fn word(&mut self, address_type: u32, value: u16) {
+        self.bits(1, 1);
+        self.bits(address_type, 2);
+        self.bits(value as u32 & 0xff, 8);
+        self.bits(value as u32 >> 8, 8);
+    }
+
+    fn special(&mut self, control: u32, a_field: Option<(u32, u16)>, b_field: Option<&str>) {
+        self.bits(0b100, 3);
+        self.bits(control, 4);
+        if let Some((address_type, value)) = a_field {
+            self.bits(address_type, 2);
+            self.bits(value as u32 & 0xff, 8);
+            self.bits(value as u32 >> 8, 8);
+        }
+        if let Some(name) = b_field {
+            let name: Vec<u8> = name.bytes().take(MAX_NAME_LENGTH).collect();
+            self.bits(name.len() as u32, 3);
+            for c in name {
+                self.bits(c as u32, 8);
+            }
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, width: u32) -> io::Result<u32> {
+        let mut value = 0;
+        for _ in 0..width {
+            let byte = *self.bytes.get(self.pos / 8)
+                .ok_or_else(|| invalid("REL file ends in the middle of an item".to_string()))?;
+            value = value << 1 | ((byte >> (7 - self.pos % 8)) & 1) as u32;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.pos = self.pos.div_ceil(8) * 8;
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos / 8 >= self.bytes.len()
+    }
+
+    fn word(&mut self) -> io::Result<u16> {
+        let lo = self.bits(8)?;
+        let hi = self.bits(8)?;
+        Ok((hi << 8 | lo) as u16)
+    }
+
+    fn name(&mut self) -> io::Result<String> {
+        let length = self.bits(3)?;
+        let mut name = String::new();
+        for _ in 0..length {
+            name.push(self.bits(8)? as u8 as char);
+        }
+        Ok(name)
+    }
+}
+
+struct ModuleReader {
+    name: Option<String>,
+    code_size: u16,
+    data_size: u16,
+    memory: BTreeMap<(Segment, u16), u8>,
+    relocations: BTreeMap<(Segment, u16), Segment>,
+    addends: HashMap<(Segment, u16), i32>,
+    chains: Vec<(String, Segment, u16)>,
+    publics: Vec<(String, Segment, u16)>,
+    start: Option<(Segment, u16)>,
+}
+
+impl ModuleReader {
+    fn new() -> ModuleReader {
+        ModuleReader {
+            name: None,
+            code_size: 0,
+            data_size: 0,
+            memory: BTreeMap::new(),
+            relocations: BTreeMap::new(),
+            addends: HashMap::new(),
+            chains: vec!(),
+            publics: vec!(),
+            start: None,
+        }
+    }
+
+    //Returns false at the end-of-file item rather than at the end of a module
+    fn read(&mut self, bits: &mut BitReader) -> io::Result<bool> {
+        let mut location = (Segment::Absolute, 0u16);
+        let mut pending_addend = None;
+        loop {
+            if bits.at_end() {
+                return Ok(false);
+            }
+            if bits.bits(1)? == 0 {
+                let byte = bits.bits(8)? as u8;
+                //the first reference to an external is loaded as two plain bytes
+                if let Some(addend) = pending_addend.take() {
+                    self.addends.insert(location, addend);
+                }
+                self.memory.insert(location, byte);
+                location.1 = location.1.wrapping_add(1);
+                continue;
+            }
+            let address_type = bits.bits(2)?;
+            if address_type != 0 {
+                let segment = segment_of(address_type)?;
+                let word = bits.word()?;
+                self.relocations.insert(location, segment);
+                if let Some(addend) = pending_addend.take() {
+                    self.addends.insert(location, addend);
+                }
+                self.memory.insert(location, word as u8);
+                self.memory.insert((location.0, location.1.wrapping_add(1)), (word >> 8) as u8);
+                location.1 = location.1.wrapping_add(2);
+                continue;
+            }
+            let control = bits.bits(4)?;
+            let a_field = if (5..END_FILE).contains(&control) {
+                let segment = segment_of(bits.bits(2)?)?;
+                Some((segment, bits.word()?))
+            } else {
+                None
+            };
+            let b_field = if control <= 7 { Some(bits.name()?) } else { None };
+            let (segment, value) = a_field.unwrap_or((Segment::Absolute, 0));
+            match control {
+                PROGRAM_NAME => self.name = b_field,
+                CHAIN_EXTERNAL => self.chains.push((b_field.unwrap_or_default(), segment, value)),
+                DEFINE_ENTRY_POINT => self.publics.push((b_field.unwrap_or_default(), segment, value)),
+                EXTERNAL_MINUS_OFFSET => pending_addend = Some(-(value as i32)),
+                EXTERNAL_PLUS_OFFSET => pending_addend = Some(value as i32),
+                DATA_SIZE => self.data_size = value,
+                SET_LOCATION => location = (segment, value),
+                PROGRAM_SIZE => self.code_size = value,
+                END_MODULE => {
+                    if segment != Segment::Absolute || value != 0 {
+                        self.start = Some((segment, value));
+                    }
+                    return Ok(true);
+                },
+                END_FILE => return Ok(false),
+                //entry symbols, library requests and the like only matter to library search
+                _ => {},
+            }
+        }
+    }
+
+    fn finish(mut self) -> io::Result<ObjectModule> {
+        let mut externals = vec!();
+        for (name, segment, offset) in self.chains.drain(..).collect::<Vec<_>>() {
+            let mut link = (segment, offset);
+            loop {
+                let lo = self.memory.get(&link).cloned().unwrap_or(0);
+                let hi = self.memory.get(&(link.0, link.1.wrapping_add(1))).cloned().unwrap_or(0);
+                let next = (lo as u16) | (hi as u16) << 8;
+                let next_segment = self.relocations.remove(&link).unwrap_or(Segment::Absolute);
+                let addend = self.addends.get(&link).cloned().unwrap_or(0);
+                self.memory.insert(link, addend as u16 as u8);
+                self.memory.insert((link.0, link.1.wrapping_add(1)), (addend as u16 >> 8) as u8);
+                externals.push(ExternalRef { name: name.clone(), segment: link.0, offset: link.1, addend });
+                if next_segment == Segment::Absolute && next == 0 {
+                    break;
+                }
+                if externals.len() > 0x10000 {
+                    return Err(invalid(format!("reference chain for {} never ends", name)));
+                }
+                link = (next_segment, next);
+            }
+        }
+        externals.sort_by_key(|external| (external.segment, external.offset));
+
+        let mut chunks: Vec<Chunk> = vec!();
+        for (&(segment, offset), &byte) in &self.memory {
+            let extends = match chunks.last() {
+                Some(chunk) => chunk.segment == segment && chunk.offset as usize + chunk.bytes.len() == offset as usize,
+                None => false,
+            };
+            if !extends {
+                chunks.push(Chunk { segment, offset, bytes: vec!(), relocations: vec!() });
+            }
+            let chunk = chunks.last_mut().expect("a chunk was just pushed");
+            if let Some(&target) = self.relocations.get(&(segment, offset)) {
+                chunk.relocations.push((chunk.bytes.len(), target));
+            }
+            chunk.bytes.push(byte);
+        }
+        Ok(ObjectModule {
+            name: self.name,
+            code_size: self.code_size,
+            data_size: self.data_size,
+            chunks,
+            publics: self.publics,
+            externals,
+            start: self.start,
+        })
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use cpu::Address;
+
+use super::Assembly;
+use super::object::Segment;
+
+const HEX_RECORD_SIZE: usize = 16;
+const LISTING_BYTES_PER_LINE: usize = 4;
+
+//Flattens a memory map into one image from its lowest to its highest address, zero filling gaps
+pub fn image(memory: &BTreeMap<Address, u8>) -> Vec<u8> {
+    let (first, last) = match (memory.keys().next(), memory.keys().next_back()) {
+        (Some(&first), Some(&last)) => (first, last),
+        _ => return vec!(),
+    };
+    let mut image = vec![0; (last - first) as usize + 1];
+    for (&addr, &byte) in memory {
+        image[(addr - first) as usize] = byte;
+    }
+    image
+}
+
+//Intel HEX: data records of up to 16 bytes, then an end record carrying the start address
+pub fn write_hex<W: Write>(memory: &BTreeMap<Address, u8>, start: Option<Address>, out: &mut W) -> io::Result<()> {
+    let mut record: Vec<u8> = vec!();
+    let mut record_addr = 0u16;
+    let mut next_addr = None;
+    for (&addr, &byte) in memory {
+        if next_addr != Some(addr) || record.len() == HEX_RECORD_SIZE {
+            write_record(out, record_addr, 0x00, &record)?;
+            record.clear();
+            record_addr = addr;
+        }
+        record.push(byte);
+        next_addr = addr.checked_add(1);
+    }
+    write_record(out, record_addr, 0x00, &record)?;
+    write_record(out, start.unwrap_or(0), 0x01, &[])
+}
+
+impl Assembly {
+    pub fn write_hex<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_hex(&self.memory_map(), self.start, out)
+    }
+
+    pub fn write_listing<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for line in &self.lines {
+            let mut chunks = line.bytes.chunks(LISTING_BYTES_PER_LINE);
+            let first: Vec<String> = chunks.next().unwrap_or(&[]).iter().map(|b| format!("{:02X}", b)).collect();
+            let location = match (line.addr, line.value) {
+                (Some(addr), _) => format!("{:04X}{}", addr, segment_marker(line.segment)),
+                (None, Some(value)) => format!("={:04X}", value),
+                (None, None) => String::new(),
+            };
+            //lines produced by a macro or REPT are marked with a '+' beside the source text
+            let marker = if line.expanded { "+" } else { " " };
+            writeln!(out, "{:>5}  {:<5}  {:<12} {}{}", line.number, location, first.join(" "), marker, line.text)?;
+            //long DB/DW lines continue underneath with their own addresses
+            let mut offset = LISTING_BYTES_PER_LINE;
+            for chunk in chunks {
+                let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02X}", b)).collect();
+                let addr = line.addr.unwrap_or(0).wrapping_add(offset as u16);
+                writeln!(out, "{:>5}  {:04X}   {}", "", addr, bytes.join(" "))?;
+                offset += LISTING_BYTES_PER_LINE;
+            }
+        }
+        writeln!(out)?;
+        writeln!(out, "Symbols:")?;
+        for (name, value) in &self.symbols {
+            writeln!(out, "{:<16}{:04X}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+//As in Microsoft's listings: ' marks code relative addresses and " data relative ones
+fn segment_marker(segment: Segment) -> &'static str {
+    match segment {
+        Segment::Absolute => "",
+        Segment::Code => "'",
+        Segment::Data => "\"",
+    }
+}
+
+fn write_record<W: Write>(out: &mut W, addr: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    if data.is_empty() && kind == 0x00 {
+        return Ok(());
+    }
+    let mut sum = data.len() as u8;
+    sum = sum.wrapping_add((addr >> 8) as u8).wrapping_add(addr as u8).wrapping_add(kind);
+    write!(out, ":{:02X}{:04X}{:02X}", data.len(), addr, kind)?;
+    for byte in data {
+        write!(out, "{:02X}", byte)?;
+        sum = sum.wrapping_add(*byte);
+    }
+    writeln!(out, "{:02X}", sum.wrapping_neg())
+}
@@ -0,0 +1,61 @@
+extern crate eightyeightyemu;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use eightyeightyemu::assembler;
+use eightyeightyemu::cpu::CPU;
+use eightyeightyemu::debugger::Debugger;
+
+//Starts the monitor on a program: a .asm file is assembled first so its labels can be used,
+//anything else is loaded as a raw image at the given hex address, 0000H by default.
+//usage: monitor <file> [load address]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <file> [load address]", args[0]);
+        process::exit(2);
+    }
+    let path = Path::new(&args[1]);
+    let origin = match args.get(2) {
+        Some(addr) => match u16::from_str_radix(addr.trim_end_matches(['h', 'H']), 16) {
+            Ok(addr) => addr,
+            Err(_) => {
+                eprintln!("{} is not a hex address", addr);
+                process::exit(2);
+            }
+        },
+        None => 0,
+    };
+    let mut cpu = CPU::empty();
+    let mut symbols = BTreeMap::new();
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("asm")) {
+        let assembly = match assembler::assemble_file(path) {
+            Ok(assembly) => assembly,
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}", error);
+                }
+                process::exit(1);
+            }
+        };
+        cpu.load(assembly.origin(), &assembly.to_binary());
+        cpu.set_pc(assembly.start.unwrap_or_else(|| assembly.origin()));
+        symbols = assembly.symbols;
+    } else {
+        let program = fs::read(path).unwrap_or_else(|error| {
+            eprintln!("Unable to read {}: {}", path.display(), error);
+            process::exit(1);
+        });
+        cpu.load(origin, &program);
+        cpu.set_pc(origin);
+    }
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut debugger = Debugger::new(cpu, symbols);
+    debugger.run(stdin.lock(), &mut stdout.lock()).expect("Unable to talk to the terminal, aborting.");
+}
@@ -0,0 +1,197 @@
+//What the command line and the interactive prompt both do with a program once it's loaded
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use assembler::{self, Assembly};
+use assembler::object::ObjectModule;
+use cpu::{CPU, Address, Io, Port};
+use debugger::Debugger;
+use error::Error;
+use gdb;
+use linker;
+use machine::altair::{Altair, AltairConfig};
+use machine::cpm::{Cpm, Exit};
+use machine::invaders::SpaceInvaders;
+use trace;
+
+//Where linked programs are loaded, as for a CP/M transient program
+pub const LINK_ORIGIN: u16 = 0x100;
+//The port QEMU and most GDB stubs listen on
+pub const GDB_ADDRESS: &str = "127.0.0.1:1234";
+//About a minute of Space Invaders, long enough to get through attract mode and into a game
+pub const INVADERS_FRAMES: u64 = 3600;
+//Stops a trace of a program that never halts from filling the disk
+pub const TRACE_LIMIT: u64 = 1_000_000;
+
+//With no machine around the CPU, IN reads zero and OUT is just reported
+pub struct Console;
+
+impl Io for Console {
+    fn input(&mut self, _port: Port) -> u8 {
+        0
+    }
+
+    //The program keeps running once nobody is reading its output, as it would on real hardware
+    fn output(&mut self, port: Port, value: u8) {
+        let _ = writeln!(io::stdout(), "OUT {:02X}H: {:02X}H", port, value);
+    }
+}
+
+//println! and eprintln! panic once their stream is closed, as when the output is piped into head,
+//so the command line writes through these instead
+pub fn say(args: fmt::Arguments) -> Result<(), String> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    out.write_fmt(args).and_then(|_| out.write_all(b"\n")).and_then(|_| out.flush())
+        .map_err(|error| format!("Unable to write to stdout: {}", error))
+}
+
+//With stderr closed as well there's nowhere left to report anything
+pub fn complain(message: &str) {
+    let _ = writeln!(io::stderr(), "{}", message);
+}
+
+//Errors name the file they're about, as a compiler's would
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path).map(BufWriter::new).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, String> {
+    let mut bytes = vec!();
+    File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|error| format!("{}: {}", path.display(), error))?;
+    Ok(bytes)
+}
+
+fn write_error(path: &Path, error: io::Error) -> String {
+    format!("Unable to write {}: {}", path.display(), error)
+}
+
+//Writes the listing and either an object file or the binary and HEX images, named after `base`
+pub fn write_assembly(path: &Path, base: &Path) -> Result<Assembly, String>
+{
+    let assembly = assembler::assemble_file(path).map_err(|errors| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+    let listing_path = base.with_extension("lst");
+    let mut listing_file = create(&listing_path)?;
+    assembly.write_listing(&mut listing_file).and_then(|_| listing_file.flush()).map_err(|error| write_error(&listing_path, error))?;
+    if assembly.is_relocatable() {
+        say(format_args!("Assembled {} bytes of code and {} bytes of data for linking.", assembly.code_size, assembly.data_size))?;
+        let rel_path = base.with_extension("rel");
+        let mut rel_file = create(&rel_path)?;
+        assembly.to_object().write_rel(&mut rel_file).and_then(|_| rel_file.flush()).map_err(|error| write_error(&rel_path, error))?;
+        return Ok(assembly);
+    }
+    let binary = assembly.to_binary();
+    say(format_args!("Assembled {} bytes starting at {:#06x}.", binary.len(), assembly.origin()))?;
+    write_images(base, &binary, |out| assembly.write_hex(out))?;
+    Ok(assembly)
+}
+
+//BASE.bin and BASE.hex
+fn write_images<F>(base: &Path, binary: &[u8], write_hex: F) -> Result<(), String>
+    where F: FnOnce(&mut BufWriter<File>) -> io::Result<()>
+{
+    let bin_path = base.with_extension("bin");
+    create(&bin_path)?.write_all(binary).map_err(|error| write_error(&bin_path, error))?;
+    let hex_path = base.with_extension("hex");
+    let mut hex_file = create(&hex_path)?;
+    write_hex(&mut hex_file).and_then(|_| hex_file.flush()).map_err(|error| write_error(&hex_path, error))
+}
+
+pub fn run_monitor(cpu: CPU, symbols: BTreeMap<String, u16>) -> Result<(), String>
+{
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut debugger = Debugger::new(cpu, symbols);
+    debugger.run(stdin.lock(), &mut stdout.lock()).map_err(|error| format!("Unable to talk to the terminal: {}", error))
+}
+
+pub fn run_gdb_server(mut cpu: CPU, address: &str) -> Result<(), String>
+{
+    gdb::serve(&mut cpu, &mut Console, address).map_err(|error| format!("Unable to serve GDB: {}", error))
+}
+
+//The program's own directory is drive A:
+pub fn run_cpm(path: &Path, args: &str) -> Result<(), String>
+{
+    let program = read(path)?;
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => Path::new(".").to_path_buf(),
+    };
+    let mut cpm = Cpm::new(&program, args, &dir);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let exit = cpm.run(&mut stdin.lock(), &mut stdout.lock(), None).map_err(|error| format!("Unable to talk to the terminal: {}", error))?;
+    say(format_args!(""))?;
+    if let Exit::Fault(error) = exit {
+        return Err(format!("Stopped after {} cycles: {}", cpm.cpu().get_cycles(), error));
+    }
+    say(format_args!("{:?} after {} cycles.", exit, cpm.cpu().get_cycles()))?;
+    Ok(())
+}
+
+//Runs the CPU on the console, tracing each instruction into the file at `path`
+pub fn write_trace(cpu: &mut CPU, path: &Path, limit: u64) -> Result<(), String>
+{
+    let mut out = create(path)?;
+    match trace::run_traced(cpu, &mut Console, &mut out, limit) {
+        Ok(count) => say(format_args!("Traced {} instructions to {}.", count, path.display())),
+        Err(Error::Io(error)) => Err(write_error(path, error)),
+        Err(error) => Err(format!("Stopped after {} cycles: {}", cpu.get_cycles(), error)),
+    }
+}
+
+//Loads the program, as a tape loader would, on a fully populated Altair and starts it there
+pub fn run_altair(program: &[u8], addr: Address) -> Result<(), String>
+{
+    let mut altair = Altair::new(AltairConfig::default());
+    altair.load(addr, program);
+    match altair.run_on_terminal() {
+        Ok(()) => say(format_args!("Stopped after {} cycles.", altair.cpu().get_cycles())),
+        Err(Error::Io(error)) => Err(format!("Unable to talk to the terminal: {}", error)),
+        Err(error) => Err(format!("Stopped after {} cycles: {}", altair.cpu().get_cycles(), error)),
+    }
+}
+
+//A raw image loaded at 0000H, as the interactive prompt boots one
+pub fn run_altair_file(path: &Path) -> Result<(), String>
+{
+    run_altair(&read(path)?, 0)
+}
+
+//Plays a short scripted game headless, then saves the last frame as invaders.ppm in the ROM directory
+pub fn run_invaders(dir: &Path) -> Result<(), String>
+{
+    let mut machine = SpaceInvaders::from_rom_directory(dir).map_err(|error| format!("Unable to load the ROMs in {}: {}", dir.display(), error))?;
+    let ppm_path = dir.join("invaders.ppm");
+    let mut ppm_file = create(&ppm_path)?;
+    machine.run_demo(INVADERS_FRAMES).map_err(|error| format!("Stopped after {} frames: {}", machine.frames(), error))?;
+    say(format_args!("Ran {} frames, {} cycles; {} pixels lit, sound latches {:02X}H {:02X}H.", machine.frames(), machine.cpu().get_cycles(),
+        machine.framebuffer().lit_pixels(), machine.sounds()[0], machine.sounds()[1]))?;
+    machine.framebuffer().write_ppm(&mut ppm_file).and_then(|_| ppm_file.flush()).map_err(|error| write_error(&ppm_path, error))
+}
+
+//The linked program is named after `base`
+pub fn link_files(paths: &[&Path], base: &Path) -> Result<(), String>
+{
+    let mut modules = vec!();
+    for path in paths {
+        let file = File::open(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        modules.extend(ObjectModule::read_rel(&mut BufReader::new(file)).map_err(|error| format!("{}: {}", path.display(), error))?);
+    }
+    let linked = linker::link(&modules, LINK_ORIGIN).map_err(|errors| {
+        errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+    })?;
+    let binary = linked.to_binary();
+    say(format_args!("Linked {} modules into {} bytes starting at {:#06x}.", modules.len(), binary.len(), LINK_ORIGIN))?;
+    for (name, value) in &linked.symbols {
+        say(format_args!("{:<8}{:04X}", name, value))?;
+    }
+    write_images(base, &binary, |out| linked.write_hex(out))
+}
@@ -0,0 +1,161 @@
+//What the emulator does when it's started without a command: it asks for a file and works
+//out what to do with it from its name, asking again when that isn't enough to go on
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use cpu::CPU;
+use error::LoadError;
+use loader::load_cpu_with_instructions_from_file;
+
+use super::actions::{say, complain, GDB_ADDRESS, TRACE_LIMIT, write_assembly, write_trace, run_monitor, run_gdb_server, run_cpm, run_altair_file, run_invaders, link_files};
+
+//What a line typed at the prompt asks for
+#[derive(Debug, PartialEq)]
+enum Request {
+    Assemble(PathBuf),
+    //A CP/M program, with the rest of the line as its command tail
+    Cpm(PathBuf, String),
+    //A directory holding the Space Invaders ROMs
+    Invaders(PathBuf),
+    //A snapshot picks up where it left off, in the monitor
+    Snapshot(PathBuf),
+    //Several object files separated by spaces are linked together
+    Link(Vec<PathBuf>),
+    //Anything else is a raw image, and what to do with it is asked for next
+    Image(PathBuf),
+}
+
+fn request(line: &str) -> Request {
+    let line = line.trim();
+    let lower = line.to_ascii_lowercase();
+    if lower.ends_with(".asm") {
+        return Request::Assemble(PathBuf::from(line));
+    }
+    let mut words = line.splitn(2, char::is_whitespace);
+    let program = words.next().unwrap_or("");
+    if program.to_ascii_lowercase().ends_with(".com") {
+        return Request::Cpm(PathBuf::from(program), words.next().unwrap_or("").trim().to_string());
+    }
+    if Path::new(line).is_dir() {
+        return Request::Invaders(PathBuf::from(line));
+    }
+    if lower.ends_with(".snap") {
+        return Request::Snapshot(PathBuf::from(line));
+    }
+    if lower.ends_with(".rel") {
+        return Request::Link(line.split_whitespace().map(PathBuf::from).collect());
+    }
+    Request::Image(PathBuf::from(line))
+}
+
+fn read_answer() -> Result<String, String> {
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).map_err(|_| "Please input a valid string.".to_string())?;
+    Ok(answer)
+}
+
+//Returns the exit status, 1 when what was asked for failed
+pub fn run() -> i32 {
+    match ask() {
+        Ok(()) => 0,
+        Err(message) => {
+            complain(&message);
+            1
+        }
+    }
+}
+
+fn ask() -> Result<(), String> {
+    say(format_args!("Time for some nostalgia!"))?;
+    say(format_args!("Please put in the file we're disassembling today."))?;
+    match request(&read_answer()?) {
+        Request::Assemble(path) => assemble_file(&path),
+        Request::Cpm(path, args) => run_cpm(&path, &args),
+        Request::Invaders(dir) => run_invaders(&dir),
+        Request::Snapshot(path) => File::open(&path).map_err(LoadError::from)
+            .and_then(|file| CPU::read_snapshot(&mut BufReader::new(file)))
+            .map_err(|error| format!("{}: {}", path.display(), error))
+            .and_then(|cpu| run_monitor(cpu, BTreeMap::new())),
+        Request::Link(paths) => {
+            let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+            link_files(&paths, paths[0])
+        },
+        Request::Image(path) => run_image(&path),
+    }
+}
+
+fn assemble_file(path: &Path) -> Result<(), String> {
+    let assembly = write_assembly(path, path)?;
+    if assembly.is_relocatable() {
+        return Ok(());
+    }
+    let binary = assembly.to_binary();
+    say(format_args!("Start the monitor on the assembled program? (y/N)"))?;
+    if !read_answer()?.trim().eq_ignore_ascii_case("y") {
+        return Ok(());
+    }
+    let mut cpu = CPU::empty();
+    cpu.load(assembly.origin(), &binary);
+    cpu.set_pc(assembly.start.unwrap_or_else(|| assembly.origin()));
+    run_monitor(cpu, assembly.symbols)
+}
+
+fn run_image(path: &Path) -> Result<(), String> {
+    say(format_args!("Write a (l)isting, re-assemblable (s)ource or (t)race, start the (m)onitor, wait for (g)db or boot an (a)ltair? [l]"))?;
+    let answer = read_answer()?.trim().to_ascii_lowercase();
+    if answer == "a" {
+        return run_altair_file(path);
+    }
+    say(format_args!("Reading file into system!"))?;
+    let mut cpu = File::open(path).map_err(LoadError::from).and_then(|file| load_cpu_with_instructions_from_file(BufReader::new(file)))
+        .map_err(|error| format!("Unable to load {}: {}", path.display(), error))?;
+    if answer == "m" {
+        return run_monitor(cpu, BTreeMap::new());
+    }
+    if answer == "g" {
+        return run_gdb_server(cpu, GDB_ADDRESS);
+    }
+    if answer == "t" {
+        return write_trace(&mut cpu, &with_suffix(path, ".trace"), TRACE_LIMIT);
+    }
+    let as_source = answer == "s";
+    let out_path = with_suffix(path, if as_source { ".asm" } else { ".out" });
+    let file = File::create(&out_path).map_err(|error| format!("{}: {}", out_path.display(), error))?;
+    let mut out = BufWriter::new(file);
+    let result = if as_source {
+        cpu.write_source(0, cpu.rom_size, &mut out)
+    } else {
+        cpu.write_memory_disassembly(&mut out)
+    };
+    result.and_then(|_| out.flush()).map_err(|error| format!("Unable to write {}: {}", out_path.display(), error))
+}
+
+//Output files are named after the input, extension and all
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::path::{Path, PathBuf};
+
+    use super::{request, with_suffix, Request};
+
+    #[test]
+    fn works_out_what_a_file_is_for() {
+        assert_eq!(request("prog.ASM\n"), Request::Assemble(PathBuf::from("prog.ASM")));
+        assert_eq!(request("STAT.COM *.*  b:"), Request::Cpm(PathBuf::from("STAT.COM"), "*.*  b:".to_string()));
+        assert_eq!(request("tst8080.com"), Request::Cpm(PathBuf::from("tst8080.com"), String::new()));
+        let dir = env::temp_dir();
+        assert_eq!(request(dir.to_str().unwrap()), Request::Invaders(dir.clone()));
+        assert_eq!(request("saved.snap"), Request::Snapshot(PathBuf::from("saved.snap")));
+        assert_eq!(request("a.rel b.REL"), Request::Link(vec!(PathBuf::from("a.rel"), PathBuf::from("b.REL"))));
+        assert_eq!(request("rom.bin"), Request::Image(PathBuf::from("rom.bin")));
+        assert_eq!(with_suffix(Path::new("dir/rom.bin"), ".out"), PathBuf::from("dir/rom.bin.out"));
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use assembler;
+use assembler::image;
+use cpu::{CPU, Address, MEMORY_SIZE};
+use disassembly;
+use loader::read_hex;
+
+use self::actions::{Console, say, complain, GDB_ADDRESS, TRACE_LIMIT, run_monitor, run_gdb_server, run_cpm, run_altair, run_invaders, write_assembly, write_trace, link_files};
+
+pub mod actions;
+pub mod interactive;
+
+pub const USAGE: &str = "\
+usage: eightyeightyemu <command> [options] <file>...
+
+commands:
+  disasm [-f FORMAT] [-l ADDR] [-o PATH] [--start ADDR] [--end ADDR] [--syntax SYNTAX] FILE
+        list a program, to stdout unless -o names a file
+  run [-f FORMAT] [-l ADDR] [-e ADDR] [-m MACHINE] [--limit N] [--trace PATH] FILE [ARGS]
+        run a program; a CP/M program gets ARGS as its command tail
+  debug [-f FORMAT] [-l ADDR] [-e ADDR] [--gdb] [--listen ADDR] FILE
+        start the monitor on a program, or wait for GDB to connect
+  asm [-o BASE] FILE
+        assemble a program into BASE.lst and BASE.bin and BASE.hex, or BASE.rel
+  link [-o BASE] FILE...
+        link object files at 0100H into BASE.bin and BASE.hex
+  help
+        show this message
+
+options:
+  -f, --format FORMAT    bin, com, hex, asm or snap; guessed from the extension otherwise
+  -l, --load ADDR        where a bin image is loaded, 0000H by default
+  -e, --entry ADDR       where execution starts, the program's own start address by default
+  -o, --output PATH      where output goes; - is stdout
+  --start ADDR           first address listed, the start of the program by default
+  --end ADDR             address listing stops before, the end of the program by default
+  --syntax SYNTAX        intel, a listing with addresses and bytes; source, re-assemblable
+                         source; debug, the decoded instructions; or json, one object per
+                         instruction with its operands, cycles and branch targets
+  -m, --machine MACHINE  plain, cpm, altair or invaders; invaders takes a ROM directory
+  --limit N              instructions to run at most
+  --trace PATH           write a trace of every instruction run to PATH
+  --gdb                  serve the GDB remote protocol instead of starting the monitor
+  --listen ADDR          the address GDB connects to, 127.0.0.1:1234 by default
+
+Addresses are hex, with or without a trailing H. Options go before the files.
+With no command at all, the emulator asks what to do.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Bin,
+    Com,
+    Hex,
+    Asm,
+    Snap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Syntax {
+    Intel,
+    Source,
+    Debug,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Machine {
+    Plain,
+    Cpm,
+    Altair,
+    Invaders,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subcommand {
+    Disasm,
+    Run,
+    Debug,
+    Asm,
+    Link,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: Subcommand,
+    format: Option<Format>,
+    load: Option<Address>,
+    entry: Option<Address>,
+    output: Option<String>,
+    start: Option<Address>,
+    end: Option<Address>,
+    syntax: Syntax,
+    machine: Option<Machine>,
+    limit: Option<u64>,
+    trace: Option<String>,
+    gdb: bool,
+    listen: Option<String>,
+    files: Vec<String>,
+}
+
+//A program ready to go, and where its bytes are
+struct Program {
+    cpu: CPU,
+    symbols: BTreeMap<String, Address>,
+    start: usize,
+    end: usize,
+}
+
+//Runs the command line and returns the exit status: 2 for a bad command line, 1 when the command fails
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse(args) {
+        Ok(options) => options,
+        Err(message) => {
+            complain(&message);
+            complain("Try `eightyeightyemu help` for usage.");
+            return 2;
+        }
+    };
+    let result = match options.command {
+        Subcommand::Help => say(format_args!("{}", USAGE)),
+        Subcommand::Disasm => disasm(&options),
+        Subcommand::Run => run_program(&options),
+        Subcommand::Debug => debug(&options),
+        Subcommand::Asm => {
+            let path = Path::new(&options.files[0]);
+            let base = options.output.as_ref().map_or(path, Path::new);
+            write_assembly(path, base).map(|_| ())
+        },
+        Subcommand::Link => {
+            let paths: Vec<&Path> = options.files.iter().map(Path::new).collect();
+            let base = options.output.as_ref().map_or(paths[0], Path::new);
+            link_files(&paths, base)
+        },
+    };
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            complain(&message);
+            1
+        }
+    }
+}
+
+//The options each command takes, by their long names
+fn allowed_options(command: Subcommand) -> &'static [&'static str] {
+    match command {
+        Subcommand::Disasm => &["format", "load", "output", "start", "end", "syntax"],
+        Subcommand::Run => &["format", "load", "entry", "machine", "limit", "trace"],
+        Subcommand::Debug => &["format", "load", "entry", "gdb", "listen"],
+        Subcommand::Asm | Subcommand::Link => &["output"],
+        Subcommand::Help => &[],
+    }
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let command = match args.first().map(|arg| arg.as_str()) {
+        Some("disasm") => Subcommand::Disasm,
+        Some("run") => Subcommand::Run,
+        Some("debug") => Subcommand::Debug,
+        Some("asm") => Subcommand::Asm,
+        Some("link") => Subcommand::Link,
+        Some("help") | Some("-h") | Some("--help") => Subcommand::Help,
+        Some(other) => return Err(format!("{} is not a command", other)),
+        None => return Err("no command given".to_string()),
+    };
+    let mut options = Options {
+        command,
+        format: None,
+        load: None,
+        entry: None,
+        output: None,
+        start: None,
+        end: None,
+        syntax: Syntax::Intel,
+        machine: None,
+        limit: None,
+        trace: None,
+        gdb: false,
+        listen: None,
+        files: vec!(),
+    };
+    let mut rest = args[1..].iter();
+    while let Some(arg) = rest.next() {
+        if !arg.starts_with('-') {
+            options.files.push(arg.clone());
+            //what follows a program to run is its command tail
+            if command == Subcommand::Run {
+                options.files.extend(rest.cloned());
+                break;
+            }
+            continue;
+        }
+        if arg == "--" {
+            options.files.extend(rest.cloned());
+            break;
+        }
+        let (name, inline_value) = match arg.find('=') {
+            Some(index) if arg.starts_with("--") => (&arg[..index], Some(arg[index + 1..].to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let name = match name {
+            "-f" | "--format" => "format",
+            "-l" | "--load" => "load",
+            "-e" | "--entry" => "entry",
+            "-o" | "--output" => "output",
+            "--start" => "start",
+            "--end" => "end",
+            "--syntax" => "syntax",
+            "-m" | "--machine" => "machine",
+            "--limit" => "limit",
+            "--trace" => "trace",
+            "--gdb" => "gdb",
+            "--listen" => "listen",
+            _ => return Err(format!("{} is not an option", name)),
+        };
+        if !allowed_options(command).contains(&name) {
+            return Err(format!("{} doesn't take --{}", args[0], name));
+        }
+        if name == "gdb" {
+            options.gdb = true;
+            continue;
+        }
+        let value = match inline_value.or_else(|| rest.next().cloned()) {
+            Some(value) => value,
+            None => return Err(format!("--{} needs a value", name)),
+        };
+        match name {
+            "format" => options.format = Some(parse_format(&value)?),
+            "load" => options.load = Some(parse_address(&value)?),
+            "entry" => options.entry = Some(parse_address(&value)?),
+            "output" => options.output = Some(value),
+            "start" => options.start = Some(parse_address(&value)?),
+            "end" => options.end = Some(parse_address(&value)?),
+            "syntax" => options.syntax = match value.as_str() {
+                "intel" => Syntax::Intel,
+                "source" => Syntax::Source,
+                "debug" => Syntax::Debug,
+                "json" => Syntax::Json,
+                _ => return Err(format!("{} is not a syntax; use intel, source, debug or json", value)),
+            },
+            "machine" => options.machine = Some(match value.as_str() {
+                "plain" => Machine::Plain,
+                "cpm" => Machine::Cpm,
+                "altair" => Machine::Altair,
+                "invaders" => Machine::Invaders,
+                _ => return Err(format!("{} is not a machine; use plain, cpm, altair or invaders", value)),
+            }),
+            "limit" => options.limit = Some(value.parse().map_err(|_| format!("{} is not a number of instructions", value))?),
+            "trace" => options.trace = Some(value),
+            "listen" => options.listen = Some(value),
+            _ => unreachable!("Option {} isn't handled", name),
+        }
+    }
+    match command {
+        Subcommand::Help => {},
+        Subcommand::Link if options.files.is_empty() => return Err("link needs at least one object file".to_string()),
+        Subcommand::Link => {},
+        Subcommand::Run if options.files.is_empty() => return Err("run needs a file".to_string()),
+        Subcommand::Run => {},
+        _ if options.files.len() != 1 => return Err(format!("{} needs exactly one file", args[0])),
+        _ => {},
+    }
+    if options.syntax == Syntax::Source && (options.start.is_some() || options.end.is_some()) {
+        return Err("source covers the whole program, so it can't take --start or --end".to_string());
+    }
+    if options.listen.is_some() && !options.gdb {
+        return Err("--listen only applies with --gdb".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "bin" => Ok(Format::Bin),
+        "com" => Ok(Format::Com),
+        "hex" => Ok(Format::Hex),
+        "asm" => Ok(Format::Asm),
+        "snap" => Ok(Format::Snap),
+        _ => Err(format!("{} is not a format; use bin, com, hex, asm or snap", value)),
+    }
+}
+
+fn parse_address(value: &str) -> Result<Address, String> {
+    let digits = value.trim_start_matches("0x").trim_end_matches(['h', 'H']);
+    Address::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hex address", value))
+}
+
+//Anything without a known extension is a raw image
+fn guess_format(path: &Path) -> Format {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+    parse_format(extension).unwrap_or(Format::Bin)
+}
+
+fn load_program(options: &Options, path: &Path) -> Result<Program, String> {
+    let format = options.format.unwrap_or_else(|| guess_format(path));
+    let mut cpu = CPU::empty();
+    let mut symbols = BTreeMap::new();
+    let (origin, bytes, start) = match format {
+        Format::Bin | Format::Com => {
+            let bytes = fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let origin = options.load.unwrap_or(if format == Format::Com { 0x100 } else { 0 });
+            (origin, bytes, None)
+        },
+        Format::Hex => {
+            let file = File::open(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let (memory, start) = read_hex(BufReader::new(file)).map_err(|error| format!("{}: {}", path.display(), error))?;
+            (memory.keys().next().cloned().unwrap_or(0), image(&memory), start)
+        },
+        Format::Asm => {
+            let assembly = assembler::assemble_file(path).map_err(|errors| {
+                errors.iter().map(|error| error.to_string()).collect::<Vec<String>>().join("\n")
+            })?;
+            if assembly.is_relocatable() {
+                return Err(format!("{} needs linking before it can be loaded", path.display()));
+            }
+            symbols = assembly.symbols.clone();
+            (assembly.origin(), assembly.to_binary(), assembly.start)
+        },
+        Format::Snap => {
+            let file = File::open(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+            let mut cpu = CPU::read_snapshot(&mut BufReader::new(file)).map_err(|error| format!("{}: {}", path.display(), error))?;
+            if let Some(entry) = options.entry {
+                cpu.set_pc(entry);
+            }
+            return Ok(Program { cpu, symbols, start: 0, end: MEMORY_SIZE });
+        },
+    };
+    if format != Format::Bin && format != Format::Com && options.load.is_some() {
+        return Err(format!("{} carries its own addresses, so it can't take --load", path.display()));
+    }
+    cpu.load(origin, &bytes);
+    cpu.set_pc(options.entry.or(start).unwrap_or(origin));
+    let end = (origin as usize + bytes.len()).min(MEMORY_SIZE);
+    Ok(Program { cpu, symbols, start: origin as usize, end })
+}
+
+//Stdout unless the options name a file
+fn open_output(options: &Options) -> Result<Box<dyn Write>, String> {
+    match options.output.as_ref() {
+        Some(path) if path != "-" => {
+            let file = File::create(path).map_err(|error| format!("{}: {}", path, error))?;
+            Ok(Box::new(BufWriter::new(file)))
+        },
+        _ => Ok(Box::new(BufWriter::new(io::stdout()))),
+    }
+}
+
+fn disasm(options: &Options) -> Result<(), String> {
+    let program = load_program(options, Path::new(&options.files[0]))?;
+    let start = options.start.map_or(program.start as Address, |start| start);
+    let end = options.end.map_or(program.end, |end| end as usize);
+    let mut out = open_output(options)?;
+    let result = match options.syntax {
+        Syntax::Source => program.cpu.write_source(program.start as Address, program.end - program.start, &mut out),
+        Syntax::Intel => program.cpu.write_disassembly(start, end, disassembly::Syntax::Intel, &program.symbols, &mut out),
+        Syntax::Debug => program.cpu.write_disassembly(start, end, disassembly::Syntax::Debug, &program.symbols, &mut out),
+        Syntax::Json => program.cpu.write_disassembly(start, end, disassembly::Syntax::Json, &program.symbols, &mut out),
+    };
+    result.and_then(|_| out.flush()).map_err(|error| format!("Unable to write the listing: {}", error))
+}
+
+fn run_program(options: &Options) -> Result<(), String> {
+    let path = Path::new(&options.files[0]);
+    let format = options.format.unwrap_or_else(|| guess_format(path));
+    let machine = options.machine.unwrap_or(if path.is_dir() {
+        Machine::Invaders
+    } else if format == Format::Com {
+        Machine::Cpm
+    } else {
+        Machine::Plain
+    });
+    if options.files.len() > 1 && machine != Machine::Cpm {
+        return Err("only CP/M programs take arguments".to_string());
+    }
+    if machine != Machine::Plain && (options.trace.is_some() || options.limit.is_some() || options.entry.is_some()) {
+        return Err("--trace, --limit and --entry only apply to the plain machine".to_string());
+    }
+    if (machine == Machine::Cpm || machine == Machine::Invaders) && (options.load.is_some() || options.format.is_some_and(|format| format != Format::Com)) {
+        return Err("CP/M programs and Space Invaders ROMs can't take --load or --format".to_string());
+    }
+    match machine {
+        Machine::Cpm => return run_cpm(path, &options.files[1..].join(" ")),
+        Machine::Invaders => return run_invaders(path),
+        Machine::Altair => {
+            let program = load_program(options, path)?;
+            let bytes: Vec<u8> = (program.start..program.end).map(|addr| program.cpu.read_memory(addr as Address)).collect();
+            return run_altair(&bytes, program.start as Address);
+        },
+        Machine::Plain => {},
+    }
+    let mut cpu = load_program(options, path)?.cpu;
+    if let Some(ref trace_path) = options.trace {
+        return write_trace(&mut cpu, Path::new(trace_path), options.limit.unwrap_or(TRACE_LIMIT));
+    }
+    let mut count = 0;
+    while !cpu.is_halted() && options.limit.map_or(true, |limit| count < limit) {
+        cpu.try_step(&mut Console).map_err(|error| format!("Stopped after {} instructions, {} cycles: {}", count, cpu.get_cycles(), error))?;
+        count += 1;
+    }
+    say(format_args!("{} after {} instructions, {} cycles.", if cpu.is_halted() { "Halted" } else { "Stopped" }, count, cpu.get_cycles()))
+}
+
+fn debug(options: &Options) -> Result<(), String> {
+    let program = load_program(options, Path::new(&options.files[0]))?;
+    if options.gdb {
+        return run_gdb_server(program.cpu, options.listen.as_ref().map_or(GDB_ADDRESS, |listen| listen.as_str()));
+    }
+    run_monitor(program.cpu, program.symbols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Format, Machine, Subcommand, Syntax};
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_commands_and_rejects_bad_ones() {
+        let options = parse(&args("disasm -f hex --start 100H --end=0x180 --syntax debug -o - prog.hex")).unwrap();
+        assert_eq!(options.command, Subcommand::Disasm);
+        assert_eq!(options.format, Some(Format::Hex));
+        assert_eq!((options.start, options.end), (Some(0x100), Some(0x180)));
+        assert_eq!(options.syntax, Syntax::Debug);
+        assert_eq!(options.output, Some("-".to_string()));
+        assert_eq!(options.files, vec!("prog.hex".to_string()));
+
+        let options = parse(&args("run -m cpm --limit 5 STAT.COM *.* -x")).unwrap();
+        assert_eq!(options.machine, Some(Machine::Cpm));
+        assert_eq!(options.limit, Some(5));
+        assert_eq!(options.files, args("STAT.COM *.* -x"));
+
+        assert!(parse(&args("disasm")).is_err());
+        assert!(parse(&args("disasm a.bin b.bin")).is_err());
+        assert!(parse(&args("disasm --gdb a.bin")).is_err());
+        assert!(parse(&args("disasm -l")).is_err());
+        assert!(parse(&args("disasm -l 10000 a.bin")).is_err());
+        assert!(parse(&args("disasm --syntax att a.bin")).is_err());
+        assert!(parse(&args("disasm --syntax source --start 0 a.bin")).is_err());
+        assert!(parse(&args("debug --listen :1234 a.bin")).is_err());
+        assert!(parse(&args("frobnicate a.bin")).is_err());
+    }
+}
@@ -1,15 +1,117 @@
-use super::{CPU, ExecutionError};
+use super::{CPU, WrongInstructionType};
 use super::instruction::Instruction;
 use super::register::{Register, RegisterPair};
 
-pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction) -> Result<(), ExecutionError> {
+pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction) -> Result<(), WrongInstructionType> {
     match instruction {
         Instruction::ADD(reg) => execute_add(cpu, reg, false),
         Instruction::ADC(reg) => execute_add(cpu, reg, true),
-        _ => Err(ExecutionError::WrongInstructionType)
+        Instruction::ADI(val) => add(cpu, val, false),
+        Instruction::ACI(val) => add(cpu, val, true),
+        Instruction::SUB(reg) => execute_sub(cpu, reg, false),
+        Instruction::SBB(reg) => execute_sub(cpu, reg, true),
+        Instruction::SUI(val) => sub(cpu, val, false),
+        Instruction::SBI(val) => sub(cpu, val, true),
+        Instruction::INR(reg) => execute_inr(cpu, reg),
+        Instruction::DCR(reg) => execute_dcr(cpu, reg),
+        Instruction::INX(pair) => {
+            let val = cpu.get_register_pair(pair).wrapping_add(1);
+            cpu.set_register_pair(pair, val);
+            Ok(())
+        },
+        Instruction::DCX(pair) => {
+            let val = cpu.get_register_pair(pair).wrapping_sub(1);
+            cpu.set_register_pair(pair, val);
+            Ok(())
+        },
+        Instruction::DAD(pair) => execute_dad(cpu, pair),
+        Instruction::DAA => execute_daa(cpu),
+        _ => Err(WrongInstructionType)
     }
 }
 
-fn execute_add(cpu: &mut CPU, reg: Register, add_carry: bool) -> Result<(), ExecutionError> {
+fn execute_add(cpu: &mut CPU, reg: Register, add_carry: bool) -> Result<(), WrongInstructionType> {
+    let val = cpu.read_register(reg);
+    add(cpu, val, add_carry)
+}
+
+fn add(cpu: &mut CPU, val: u8, add_carry: bool) -> Result<(), WrongInstructionType> {
+    let a = cpu.get_register(&Register::A);
+    let carry = (add_carry && cpu.flags.cy) as u8;
+    let result = add_with_flags(cpu, a, val, carry);
+    cpu.set_register(Register::A, result);
+    Ok(())
+}
+
+//The 8080 subtracts by adding the complement, so the flags come out of the same adder
+//with carry inverted to mean borrow.
+fn execute_sub(cpu: &mut CPU, reg: Register, sub_borrow: bool) -> Result<(), WrongInstructionType> {
+    let val = cpu.read_register(reg);
+    sub(cpu, val, sub_borrow)
+}
+
+fn sub(cpu: &mut CPU, val: u8, sub_borrow: bool) -> Result<(), WrongInstructionType> {
+    let a = cpu.get_register(&Register::A);
+    let borrow = sub_borrow && cpu.flags.cy;
+    let result = sub_with_flags(cpu, a, val, borrow);
+    cpu.set_register(Register::A, result);
+    Ok(())
+}
+
+pub fn add_with_flags(cpu: &mut CPU, a: u8, val: u8, carry: u8) -> u8 {
+    let sum = a as u16 + val as u16 + carry as u16;
+    let result = sum as u8;
+    cpu.flags.cy = sum > 0xff;
+    cpu.flags.ac = (a & 0xf) + (val & 0xf) + carry > 0xf;
+    cpu.flags.set_zsp(result);
+    result
+}
+
+pub fn sub_with_flags(cpu: &mut CPU, a: u8, val: u8, borrow: bool) -> u8 {
+    let result = add_with_flags(cpu, a, !val, !borrow as u8);
+    cpu.flags.cy = !cpu.flags.cy;
+    result
+}
+
+//INR and DCR leave carry alone
+fn execute_inr(cpu: &mut CPU, reg: Register) -> Result<(), WrongInstructionType> {
+    let val = cpu.read_register(reg);
+    let result = val.wrapping_add(1);
+    cpu.flags.ac = val & 0xf == 0xf;
+    cpu.flags.set_zsp(result);
+    cpu.set_register(reg, result);
+    Ok(())
+}
+
+fn execute_dcr(cpu: &mut CPU, reg: Register) -> Result<(), WrongInstructionType> {
+    let val = cpu.read_register(reg);
+    let result = val.wrapping_sub(1);
+    cpu.flags.ac = val & 0xf != 0;
+    cpu.flags.set_zsp(result);
+    cpu.set_register(reg, result);
+    Ok(())
+}
+
+fn execute_dad(cpu: &mut CPU, pair: RegisterPair) -> Result<(), WrongInstructionType> {
+    let sum = cpu.get_register_pair(RegisterPair::HL) as u32 + cpu.get_register_pair(pair) as u32;
+    cpu.flags.cy = sum > 0xffff;
+    cpu.set_register_pair(RegisterPair::HL, sum as u16);
     Ok(())
-}
\ No newline at end of file
+}
+
+fn execute_daa(cpu: &mut CPU) -> Result<(), WrongInstructionType> {
+    let a = cpu.get_register(&Register::A);
+    let mut correction = 0;
+    let mut carry = cpu.flags.cy;
+    if cpu.flags.ac || a & 0xf > 9 {
+        correction |= 0x06;
+    }
+    if carry || a >> 4 > 9 || (a >> 4 >= 9 && a & 0xf > 9) {
+        correction |= 0x60;
+        carry = true;
+    }
+    let result = add_with_flags(cpu, a, correction, 0);
+    cpu.flags.cy = carry;
+    cpu.set_register(Register::A, result);
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use super::{CPU, WrongInstructionType};
+use super::instruction::{Instruction, TAKEN_CYCLES};
+use super::register::RegisterPair;
+
+pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction) -> Result<(), WrongInstructionType> {
+    match instruction {
+        Instruction::JMP(addr) => cpu.pc = addr,
+        Instruction::JCOND(condition, addr) => if cpu.flags.is_met(condition) {
+            cpu.pc = addr;
+        },
+        Instruction::CALL(addr) => call(cpu, addr),
+        Instruction::CCOND(condition, addr) => if cpu.flags.is_met(condition) {
+            cpu.cycles += TAKEN_CYCLES;
+            call(cpu, addr);
+        },
+        Instruction::RET => cpu.pc = cpu.pop(),
+        Instruction::RETCOND(condition) => if cpu.flags.is_met(condition) {
+            cpu.cycles += TAKEN_CYCLES;
+            cpu.pc = cpu.pop();
+        },
+        Instruction::RST(vector) => call(cpu, vector as u16 * 8),
+        Instruction::PCHL => cpu.pc = cpu.get_register_pair(RegisterPair::HL),
+        _ => return Err(WrongInstructionType)
+    }
+    Ok(())
+}
+
+//The PC has already moved past the call, so that is the return address pushed
+fn call(cpu: &mut CPU, addr: u16) {
+    let pc = cpu.pc;
+    cpu.push(pc);
+    cpu.pc = addr;
+}
@@ -1,7 +1,9 @@
-use std::convert::From;
+use core::convert::From;
+use core::fmt;
 
 pub type ConditionOpCode = i8;
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum ConditionOp {
     NZ,
     Z,
@@ -13,13 +15,14 @@ pub enum ConditionOp {
     M,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Condition {
-    z: bool,
-    s: bool,
-    p: bool,
-    cy: bool,
-    ac: bool,
+    pub z: bool,
+    pub s: bool,
+    pub p: bool,
+    pub cy: bool,
+    pub ac: bool,
 }
 
 impl Condition {
@@ -32,11 +35,46 @@ impl Condition {
             ac: false,
         }
     }
+
+    //The flag byte PUSH PSW stores: S Z 0 AC 0 P 1 CY
+    pub fn to_byte(self) -> u8 {
+        (self.s as u8) << 7 | (self.z as u8) << 6 | (self.ac as u8) << 4 | (self.p as u8) << 2 | 0b10 | self.cy as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Condition {
+        Condition {
+            z: byte & 0x40 != 0,
+            s: byte & 0x80 != 0,
+            p: byte & 0x04 != 0,
+            cy: byte & 0x01 != 0,
+            ac: byte & 0x10 != 0,
+        }
+    }
+
+    //Zero, sign and parity all follow from the result alone
+    pub fn set_zsp(&mut self, value: u8) {
+        self.z = value == 0;
+        self.s = value & 0x80 != 0;
+        self.p = value.count_ones() & 1 == 0;
+    }
+
+    pub fn is_met(&self, op: ConditionOp) -> bool {
+        match op {
+            ConditionOp::NZ => !self.z,
+            ConditionOp::Z => self.z,
+            ConditionOp::NC => !self.cy,
+            ConditionOp::C => self.cy,
+            ConditionOp::PO => !self.p,
+            ConditionOp::PE => self.p,
+            ConditionOp::P => !self.s,
+            ConditionOp::M => self.s,
+        }
+    }
 }
 
 impl From<ConditionOpCode> for ConditionOp {
     fn from(op: ConditionOpCode) -> Self {
-        match op {
+        match op & 0b111 {
             0b000 => ConditionOp::NZ,
             0b001 => ConditionOp::Z,
             0b010 => ConditionOp::NC,
@@ -44,8 +82,7 @@ impl From<ConditionOpCode> for ConditionOp {
             0b100 => ConditionOp::PO,
             0b101 => ConditionOp::PE,
             0b110 => ConditionOp::P,
-            0b111 => ConditionOp::M,
-            _ => unreachable!(),
+            _ => ConditionOp::M,
         }
     }
 }
@@ -61,7 +98,22 @@ impl From<ConditionOp> for ConditionOpCode {
             ConditionOp::PE   => 0b101,
             ConditionOp::P    => 0b110,
             ConditionOp::M    => 0b111,
-            _ => unreachable!(),
         }
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for ConditionOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ConditionOp::NZ => "NZ",
+            ConditionOp::Z  => "Z",
+            ConditionOp::NC => "NC",
+            ConditionOp::C  => "C",
+            ConditionOp::PO => "PO",
+            ConditionOp::PE => "PE",
+            ConditionOp::P  => "P",
+            ConditionOp::M  => "M",
+        };
+        f.write_str(name)
+    }
+}
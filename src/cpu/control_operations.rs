@@ -0,0 +1,52 @@
+use super::{CPU, Access, WrongInstructionType, Io};
+use super::condition::Condition;
+use super::instruction::Instruction;
+use super::register::{Register, RegisterPair};
+
+//Stack, I/O and machine control
+pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction, io: &mut dyn Io) -> Result<(), WrongInstructionType> {
+    match instruction {
+        Instruction::PUSH(pair) => {
+            let val = cpu.get_register_pair(pair);
+            cpu.push(val);
+        },
+        Instruction::POP(pair) => {
+            let val = cpu.pop();
+            cpu.set_register_pair(pair, val);
+        },
+        Instruction::PUSH_PSW => {
+            let psw = (cpu.get_register(&Register::A) as u16) << 8 | cpu.flags.to_byte() as u16;
+            cpu.push(psw);
+        },
+        Instruction::POP_PSW => {
+            let psw = cpu.pop();
+            cpu.flags = Condition::from_byte(psw as u8);
+            cpu.set_register(Register::A, (psw >> 8) as u8);
+        },
+        Instruction::XTHL => {
+            let sp = cpu.sp;
+            let top = cpu.bus_read_word(sp);
+            let hl = cpu.get_register_pair(RegisterPair::HL);
+            cpu.bus_write_word(sp, hl);
+            cpu.set_register_pair(RegisterPair::HL, top);
+        },
+        Instruction::SPHL => cpu.sp = cpu.get_register_pair(RegisterPair::HL),
+        Instruction::IN(port) => {
+            let value = io.input(port);
+            cpu.accesses.push(Access::Input { port, value });
+            cpu.set_register(Register::A, value);
+        },
+        Instruction::OUT(port) => {
+            let value = cpu.get_register(&Register::A);
+            cpu.accesses.push(Access::Output { port, value });
+            io.output(port, value);
+        },
+        Instruction::EI => cpu.interrupts_enabled = true,
+        Instruction::DI => cpu.interrupts_enabled = false,
+        Instruction::HLT => cpu.halted = true,
+        //RIM and SIM only exist on the 8085
+        Instruction::NOP | Instruction::RIM | Instruction::SIM => {},
+        _ => return Err(WrongInstructionType)
+    }
+    Ok(())
+}
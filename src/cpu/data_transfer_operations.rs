@@ -0,0 +1,48 @@
+use super::{CPU, WrongInstructionType, create_addr};
+use super::instruction::Instruction;
+use super::register::{Register, RegisterPair};
+
+pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction) -> Result<(), WrongInstructionType> {
+    match instruction {
+        Instruction::MOV(dst, src) => {
+            let val = cpu.read_register(src);
+            cpu.set_register(dst, val);
+        },
+        Instruction::MVI(dst, val) => cpu.set_register(dst, val),
+        Instruction::LXI(pair, (hi, lo)) => cpu.set_register_pair(pair, create_addr(lo, hi)),
+        Instruction::LDA(addr) => {
+            let val = cpu.bus_read(addr);
+            cpu.set_register(Register::A, val);
+        },
+        Instruction::STA(addr) => {
+            let val = cpu.get_register(&Register::A);
+            cpu.bus_write(addr, val);
+        },
+        Instruction::LHLD(addr) => {
+            let val = cpu.bus_read_word(addr);
+            cpu.set_register_pair(RegisterPair::HL, val);
+        },
+        Instruction::SHLD(addr) => {
+            let val = cpu.get_register_pair(RegisterPair::HL);
+            cpu.bus_write_word(addr, val);
+        },
+        Instruction::LDAX(pair) => {
+            let addr = cpu.get_register_pair(pair);
+            let val = cpu.bus_read(addr);
+            cpu.set_register(Register::A, val);
+        },
+        Instruction::STAX(pair) => {
+            let addr = cpu.get_register_pair(pair);
+            let val = cpu.get_register(&Register::A);
+            cpu.bus_write(addr, val);
+        },
+        Instruction::XCHG => {
+            let de = cpu.get_register_pair(RegisterPair::DE);
+            let hl = cpu.get_register_pair(RegisterPair::HL);
+            cpu.set_register_pair(RegisterPair::DE, hl);
+            cpu.set_register_pair(RegisterPair::HL, de);
+        },
+        _ => return Err(WrongInstructionType)
+    }
+    Ok(())
+}
@@ -1,10 +1,18 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
 use super::register::{Register, RegisterPair, RegisterOp, RegisterPairOp};
 use super::condition::{ConditionOp, ConditionOpCode};
 use super::{Address, Port};
+use error::EncodeError;
 
-pub type Word = u8;
+//Taking a conditional call or return costs this many cycles more than skipping it
+pub const TAKEN_CYCLES: u64 = 6;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Instruction {
     MOV(Register, Register),
     MVI(Register, u8),
@@ -95,4 +103,397 @@ impl Instruction {
             _ => 1
         }
     }
-}
\ No newline at end of file
+
+    //Clock cycles on an 8080; conditional calls and returns take 6 more when the condition holds
+    pub fn get_cycles(&self) -> u64 {
+        match self {
+            Instruction::MOV(Register::M, _) | Instruction::MOV(_, Register::M) => 7,
+            Instruction::MOV(_, _) => 5,
+            Instruction::MVI(Register::M, _) => 10,
+            Instruction::MVI(_, _) => 7,
+            Instruction::LXI(_, _) => 10,
+            Instruction::LDA(_) | Instruction::STA(_) => 13,
+            Instruction::LHLD(_) | Instruction::SHLD(_) => 16,
+            Instruction::LDAX(_) | Instruction::STAX(_) => 7,
+            Instruction::ADD(Register::M) | Instruction::ADC(Register::M) | Instruction::SUB(Register::M) |
+            Instruction::SBB(Register::M) | Instruction::ANA(Register::M) | Instruction::XRA(Register::M) |
+            Instruction::ORA(Register::M) | Instruction::CMP(Register::M) => 7,
+            Instruction::ADD(_) | Instruction::ADC(_) | Instruction::SUB(_) | Instruction::SBB(_) |
+            Instruction::ANA(_) | Instruction::XRA(_) | Instruction::ORA(_) | Instruction::CMP(_) => 4,
+            Instruction::ADI(_) | Instruction::ACI(_) | Instruction::SUI(_) | Instruction::SBI(_) |
+            Instruction::ANI(_) | Instruction::XRI(_) | Instruction::ORI(_) | Instruction::CPI(_) => 7,
+            Instruction::INR(Register::M) | Instruction::DCR(Register::M) => 10,
+            Instruction::INR(_) | Instruction::DCR(_) => 5,
+            Instruction::INX(_) | Instruction::DCX(_) => 5,
+            Instruction::DAD(_) => 10,
+            Instruction::JMP(_) | Instruction::JCOND(_, _) => 10,
+            Instruction::CALL(_) => 17,
+            Instruction::CCOND(_, _) => 11,
+            Instruction::RET => 10,
+            Instruction::RETCOND(_) => 5,
+            Instruction::RST(_) => 11,
+            Instruction::PCHL | Instruction::SPHL => 5,
+            Instruction::PUSH(_) | Instruction::PUSH_PSW => 11,
+            Instruction::POP(_) | Instruction::POP_PSW => 10,
+            Instruction::XTHL => 18,
+            Instruction::IN(_) | Instruction::OUT(_) => 10,
+            Instruction::HLT => 7,
+            _ => 4,
+        }
+    }
+
+    //Canonical opcode bytes for this instruction, operands little-endian as in memory. Some
+    //variants have no opcode: MOV M,M is where HLT sits, LDAX and STAX only take B and D,
+    //PUSH and POP have PSW where SP would be and there are only 8 restarts.
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        let reg = |reg: Register| RegisterOp::from(reg);
+        let pair = |pair: RegisterPair| RegisterPairOp::from(pair) << 4;
+        let cond = |cond: ConditionOp| (ConditionOpCode::from(cond) as u8) << 3;
+        let lo = |addr: u16| addr as u8;
+        let hi = |addr: u16| (addr >> 8) as u8;
+        let bytes = match *self {
+            Instruction::MOV(Register::M, Register::M)
+            | Instruction::LDAX(RegisterPair::HL) | Instruction::LDAX(RegisterPair::SP)
+            | Instruction::STAX(RegisterPair::HL) | Instruction::STAX(RegisterPair::SP)
+            | Instruction::PUSH(RegisterPair::SP) | Instruction::POP(RegisterPair::SP) => return Err(EncodeError(*self)),
+            Instruction::RST(vector) if vector > 7 => return Err(EncodeError(*self)),
+            Instruction::MOV(dst, src) => vec!(0x40 | reg(dst) << 3 | reg(src)),
+            Instruction::MVI(dst, val) => vec!(0x06 | reg(dst) << 3, val),
+            Instruction::LXI(dst, (hi_byte, lo_byte)) => vec!(0x01 | pair(dst), lo_byte, hi_byte),
+            Instruction::LDA(addr) => vec!(0x3a, lo(addr), hi(addr)),
+            Instruction::STA(addr) => vec!(0x32, lo(addr), hi(addr)),
+            Instruction::LHLD(addr) => vec!(0x2a, lo(addr), hi(addr)),
+            Instruction::SHLD(addr) => vec!(0x22, lo(addr), hi(addr)),
+            Instruction::LDAX(src) => vec!(0x0a | pair(src)),
+            Instruction::STAX(dst) => vec!(0x02 | pair(dst)),
+            Instruction::XCHG => vec!(0xeb),
+            Instruction::ADD(src) => vec!(0x80 | reg(src)),
+            Instruction::ADI(val) => vec!(0xc6, val),
+            Instruction::ADC(src) => vec!(0x88 | reg(src)),
+            Instruction::ACI(val) => vec!(0xce, val),
+            Instruction::SUB(src) => vec!(0x90 | reg(src)),
+            Instruction::SUI(val) => vec!(0xd6, val),
+            Instruction::SBB(src) => vec!(0x98 | reg(src)),
+            Instruction::SBI(val) => vec!(0xde, val),
+            Instruction::INR(dst) => vec!(0x04 | reg(dst) << 3),
+            Instruction::DCR(dst) => vec!(0x05 | reg(dst) << 3),
+            Instruction::INX(dst) => vec!(0x03 | pair(dst)),
+            Instruction::DCX(dst) => vec!(0x0b | pair(dst)),
+            Instruction::DAD(src) => vec!(0x09 | pair(src)),
+            Instruction::DAA => vec!(0x27),
+            Instruction::ANA(src) => vec!(0xa0 | reg(src)),
+            Instruction::ANI(val) => vec!(0xe6, val),
+            Instruction::ORA(src) => vec!(0xb0 | reg(src)),
+            Instruction::ORI(val) => vec!(0xf6, val),
+            Instruction::XRA(src) => vec!(0xa8 | reg(src)),
+            Instruction::XRI(val) => vec!(0xee, val),
+            Instruction::CMP(src) => vec!(0xb8 | reg(src)),
+            Instruction::CPI(val) => vec!(0xfe, val),
+            Instruction::RLC => vec!(0x07),
+            Instruction::RRC => vec!(0x0f),
+            Instruction::RAL => vec!(0x17),
+            Instruction::RAR => vec!(0x1f),
+            Instruction::RIM => vec!(0x20),
+            Instruction::RETCOND(condition) => vec!(0xc0 | cond(condition)),
+            Instruction::RET => vec!(0xc9),
+            Instruction::SIM => vec!(0x30),
+            Instruction::CMA => vec!(0x2f),
+            Instruction::CMC => vec!(0x3f),
+            Instruction::STC => vec!(0x37),
+            Instruction::JMP(addr) => vec!(0xc3, lo(addr), hi(addr)),
+            Instruction::JCOND(condition, addr) => vec!(0xc2 | cond(condition), lo(addr), hi(addr)),
+            Instruction::CALL(addr) => vec!(0xcd, lo(addr), hi(addr)),
+            Instruction::CCOND(condition, addr) => vec!(0xc4 | cond(condition), lo(addr), hi(addr)),
+            Instruction::RST(vector) => vec!(0xc7 | vector << 3),
+            Instruction::PCHL => vec!(0xe9),
+            Instruction::PUSH(src) => vec!(0xc5 | pair(src)),
+            Instruction::PUSH_PSW => vec!(0xf5),
+            Instruction::POP(dst) => vec!(0xc1 | pair(dst)),
+            Instruction::POP_PSW => vec!(0xf1),
+            Instruction::XTHL => vec!(0xe3),
+            Instruction::SPHL => vec!(0xf9),
+            Instruction::IN(port) => vec!(0xdb, port),
+            Instruction::OUT(port) => vec!(0xd3, port),
+            Instruction::EI => vec!(0xfb),
+            Instruction::DI => vec!(0xf3),
+            Instruction::HLT => vec!(0x76),
+            Instruction::NOP => vec!(0x00),
+        };
+        Ok(bytes)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instruction::MOV(dst, src) => write!(f, "MOV {},{}", dst, src),
+            Instruction::MVI(reg, val) => write!(f, "MVI {},{}", reg, format_byte(*val)),
+            Instruction::LXI(pair, (hi, lo)) =>
+                write!(f, "LXI {},{}", pair, format_word(((*hi as u16) << 8) | *lo as u16)),
+            Instruction::LDA(addr) => write!(f, "LDA {}", format_word(*addr)),
+            Instruction::STA(addr) => write!(f, "STA {}", format_word(*addr)),
+            Instruction::LHLD(addr) => write!(f, "LHLD {}", format_word(*addr)),
+            Instruction::SHLD(addr) => write!(f, "SHLD {}", format_word(*addr)),
+            Instruction::LDAX(pair) => write!(f, "LDAX {}", pair),
+            Instruction::STAX(pair) => write!(f, "STAX {}", pair),
+            Instruction::XCHG => f.write_str("XCHG"),
+            Instruction::ADD(reg) => write!(f, "ADD {}", reg),
+            Instruction::ADI(val) => write!(f, "ADI {}", format_byte(*val)),
+            Instruction::ADC(reg) => write!(f, "ADC {}", reg),
+            Instruction::ACI(val) => write!(f, "ACI {}", format_byte(*val)),
+            Instruction::SUB(reg) => write!(f, "SUB {}", reg),
+            Instruction::SUI(val) => write!(f, "SUI {}", format_byte(*val)),
+            Instruction::SBB(reg) => write!(f, "SBB {}", reg),
+            Instruction::SBI(val) => write!(f, "SBI {}", format_byte(*val)),
+            Instruction::INR(reg) => write!(f, "INR {}", reg),
+            Instruction::DCR(reg) => write!(f, "DCR {}", reg),
+            Instruction::INX(pair) => write!(f, "INX {}", pair),
+            Instruction::DCX(pair) => write!(f, "DCX {}", pair),
+            Instruction::DAD(pair) => write!(f, "DAD {}", pair),
+            Instruction::DAA => f.write_str("DAA"),
+            Instruction::ANA(reg) => write!(f, "ANA {}", reg),
+            Instruction::ANI(val) => write!(f, "ANI {}", format_byte(*val)),
+            Instruction::ORA(reg) => write!(f, "ORA {}", reg),
+            Instruction::ORI(val) => write!(f, "ORI {}", format_byte(*val)),
+            Instruction::XRA(reg) => write!(f, "XRA {}", reg),
+            Instruction::XRI(val) => write!(f, "XRI {}", format_byte(*val)),
+            Instruction::CMP(reg) => write!(f, "CMP {}", reg),
+            Instruction::CPI(val) => write!(f, "CPI {}", format_byte(*val)),
+            Instruction::RLC => f.write_str("RLC"),
+            Instruction::RRC => f.write_str("RRC"),
+            Instruction::RAL => f.write_str("RAL"),
+            Instruction::RAR => f.write_str("RAR"),
+            Instruction::RIM => f.write_str("RIM"),
+            Instruction::RETCOND(cond) => write!(f, "R{}", cond),
+            Instruction::RET => f.write_str("RET"),
+            Instruction::SIM => f.write_str("SIM"),
+            Instruction::CMA => f.write_str("CMA"),
+            Instruction::CMC => f.write_str("CMC"),
+            Instruction::STC => f.write_str("STC"),
+            Instruction::JMP(addr) => write!(f, "JMP {}", format_word(*addr)),
+            Instruction::JCOND(cond, addr) => write!(f, "J{} {}", cond, format_word(*addr)),
+            Instruction::CALL(addr) => write!(f, "CALL {}", format_word(*addr)),
+            Instruction::CCOND(cond, addr) => write!(f, "C{} {}", cond, format_word(*addr)),
+            Instruction::RST(vector) => write!(f, "RST {}", vector),
+            Instruction::PCHL => f.write_str("PCHL"),
+            Instruction::PUSH(pair) => write!(f, "PUSH {}", pair),
+            Instruction::PUSH_PSW => f.write_str("PUSH PSW"),
+            Instruction::POP(pair) => write!(f, "POP {}", pair),
+            Instruction::POP_PSW => f.write_str("POP PSW"),
+            Instruction::XTHL => f.write_str("XTHL"),
+            Instruction::SPHL => f.write_str("SPHL"),
+            Instruction::IN(port) => write!(f, "IN {}", format_byte(*port)),
+            Instruction::OUT(port) => write!(f, "OUT {}", format_byte(*port)),
+            Instruction::EI => f.write_str("EI"),
+            Instruction::DI => f.write_str("DI"),
+            Instruction::HLT => f.write_str("HLT"),
+            Instruction::NOP => f.write_str("NOP"),
+        }
+    }
+}
+
+//An instruction's operands, each with its kind, in the order Intel syntax writes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Operand {
+    Register(Register),
+    RegisterPair(RegisterPair),
+    //The accumulator and flags, as pushed and popped together
+    Psw,
+    //Data in the instruction itself: a byte, LXI's word or RST's vector
+    Immediate(u16),
+    Address(Address),
+    Port(Port),
+    //Conditional jumps, calls and returns carry theirs in the mnemonic as well
+    Condition(ConditionOp),
+}
+
+impl Instruction {
+    //The mnemonic as written in Intel syntax, condition included
+    pub fn mnemonic(&self) -> String {
+        let text = self.to_string();
+        text.split(' ').next().unwrap_or("").to_string()
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        match *self {
+            Instruction::MOV(dst, src) => vec!(Operand::Register(dst), Operand::Register(src)),
+            Instruction::MVI(reg, val) => vec!(Operand::Register(reg), Operand::Immediate(val as u16)),
+            Instruction::LXI(pair, (hi, lo)) => vec!(Operand::RegisterPair(pair), Operand::Immediate((hi as u16) << 8 | lo as u16)),
+            Instruction::LDA(addr) | Instruction::STA(addr) | Instruction::LHLD(addr) | Instruction::SHLD(addr) |
+            Instruction::JMP(addr) | Instruction::CALL(addr) => vec!(Operand::Address(addr)),
+            Instruction::LDAX(pair) | Instruction::STAX(pair) | Instruction::INX(pair) | Instruction::DCX(pair) |
+            Instruction::DAD(pair) | Instruction::PUSH(pair) | Instruction::POP(pair) => vec!(Operand::RegisterPair(pair)),
+            Instruction::ADD(reg) | Instruction::ADC(reg) | Instruction::SUB(reg) | Instruction::SBB(reg) |
+            Instruction::INR(reg) | Instruction::DCR(reg) | Instruction::ANA(reg) | Instruction::ORA(reg) |
+            Instruction::XRA(reg) | Instruction::CMP(reg) => vec!(Operand::Register(reg)),
+            Instruction::ADI(val) | Instruction::ACI(val) | Instruction::SUI(val) | Instruction::SBI(val) |
+            Instruction::ANI(val) | Instruction::ORI(val) | Instruction::XRI(val) | Instruction::CPI(val) |
+            Instruction::RST(val) => vec!(Operand::Immediate(val as u16)),
+            Instruction::RETCOND(cond) => vec!(Operand::Condition(cond)),
+            Instruction::JCOND(cond, addr) | Instruction::CCOND(cond, addr) => vec!(Operand::Condition(cond), Operand::Address(addr)),
+            Instruction::PUSH_PSW | Instruction::POP_PSW => vec!(Operand::Psw),
+            Instruction::IN(port) | Instruction::OUT(port) => vec!(Operand::Port(port)),
+            _ => vec!(),
+        }
+    }
+
+    //Where a jump, call or restart can go other than the next instruction; returns and
+    //PCHL go somewhere only known at run time
+    pub fn branch_targets(&self) -> Vec<Address> {
+        match *self {
+            Instruction::JMP(addr) | Instruction::JCOND(_, addr) | Instruction::CALL(addr) | Instruction::CCOND(_, addr) => vec!(addr),
+            Instruction::RST(vector) => vec!(vector as Address * 8),
+            _ => vec!(),
+        }
+    }
+
+    //Clock cycles when a conditional call or return is taken; the same as get_cycles for everything else
+    pub fn get_cycles_taken(&self) -> u64 {
+        match self {
+            Instruction::CCOND(_, _) | Instruction::RETCOND(_) => self.get_cycles() + TAKEN_CYCLES,
+            _ => self.get_cycles(),
+        }
+    }
+}
+
+//Intel hex literals need a leading digit, so 0xff is written as 0FFH
+pub fn format_byte(val: u8) -> String {
+    let digits = format!("{:02X}", val);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        format!("0{}H", digits)
+    } else {
+        format!("{}H", digits)
+    }
+}
+
+pub fn format_word(val: u16) -> String {
+    let digits = format!("{:04X}", val);
+    if digits.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        format!("0{}H", digits)
+    } else {
+        format!("{}H", digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::register::{Register, RegisterPair};
+    use cpu::condition::ConditionOp;
+    use cpu::decode::decode;
+    use error::EncodeError;
+    #[cfg(feature = "std")]
+    use cpu::NoIo;
+    #[cfg(feature = "std")]
+    use error::{DecodeError, ExecutionError};
+    #[cfg(feature = "std")]
+    use testing::cpu_from_assembly;
+    use super::Instruction;
+
+    const REGISTERS: [Register; 8] = [Register::A, Register::B, Register::C, Register::D,
+        Register::E, Register::H, Register::L, Register::M];
+    const PAIRS: [RegisterPair; 4] = [RegisterPair::BC, RegisterPair::DE, RegisterPair::HL, RegisterPair::SP];
+    const CONDITIONS: [ConditionOp; 8] = [ConditionOp::NZ, ConditionOp::Z, ConditionOp::NC, ConditionOp::C,
+        ConditionOp::PO, ConditionOp::PE, ConditionOp::P, ConditionOp::M];
+    const BYTES: [u8; 6] = [0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff];
+    const WORDS: [u16; 6] = [0x0000, 0x0001, 0x00ff, 0x1234, 0x8000, 0xffff];
+
+    fn every_instruction() -> Vec<Instruction> {
+        let mut all = vec!(
+            Instruction::XCHG, Instruction::DAA, Instruction::RLC, Instruction::RRC, Instruction::RAL,
+            Instruction::RAR, Instruction::RIM, Instruction::RET, Instruction::SIM, Instruction::CMA,
+            Instruction::CMC, Instruction::STC, Instruction::PCHL, Instruction::PUSH_PSW, Instruction::POP_PSW,
+            Instruction::XTHL, Instruction::SPHL, Instruction::EI, Instruction::DI, Instruction::HLT, Instruction::NOP,
+        );
+        for &dst in &REGISTERS {
+            for &src in &REGISTERS {
+                if dst != Register::M || src != Register::M {
+                    all.push(Instruction::MOV(dst, src));
+                }
+            }
+            for &val in &BYTES {
+                all.push(Instruction::MVI(dst, val));
+            }
+            all.extend(vec!(Instruction::ADD(dst), Instruction::ADC(dst), Instruction::SUB(dst), Instruction::SBB(dst),
+                Instruction::ANA(dst), Instruction::ORA(dst), Instruction::XRA(dst), Instruction::CMP(dst),
+                Instruction::INR(dst), Instruction::DCR(dst)));
+        }
+        for &val in &BYTES {
+            all.extend(vec!(Instruction::ADI(val), Instruction::ACI(val), Instruction::SUI(val), Instruction::SBI(val),
+                Instruction::ANI(val), Instruction::ORI(val), Instruction::XRI(val), Instruction::CPI(val),
+                Instruction::IN(val), Instruction::OUT(val)));
+        }
+        for &pair in &PAIRS {
+            all.extend(vec!(Instruction::INX(pair), Instruction::DCX(pair), Instruction::DAD(pair)));
+            for &word in &WORDS {
+                all.push(Instruction::LXI(pair, ((word >> 8) as u8, word as u8)));
+            }
+            if pair != RegisterPair::SP {
+                all.extend(vec!(Instruction::PUSH(pair), Instruction::POP(pair)));
+            }
+            if pair == RegisterPair::BC || pair == RegisterPair::DE {
+                all.extend(vec!(Instruction::LDAX(pair), Instruction::STAX(pair)));
+            }
+        }
+        for &addr in &WORDS {
+            all.extend(vec!(Instruction::LDA(addr), Instruction::STA(addr), Instruction::LHLD(addr),
+                Instruction::SHLD(addr), Instruction::JMP(addr), Instruction::CALL(addr)));
+            for &condition in &CONDITIONS {
+                all.extend(vec!(Instruction::JCOND(condition, addr), Instruction::CCOND(condition, addr)));
+            }
+        }
+        for &condition in &CONDITIONS {
+            all.push(Instruction::RETCOND(condition));
+        }
+        for vector in 0..8 {
+            all.push(Instruction::RST(vector));
+        }
+        all
+    }
+
+    #[test]
+    fn decoding_encoded_instruction_gives_it_back() {
+        for instruction in every_instruction() {
+            let bytes = instruction.encode().unwrap();
+            assert_eq!(bytes.len(), instruction.get_size() as usize, "{}", instruction);
+            assert_eq!(decode(&bytes, 0), Ok((instruction, bytes.len())), "{:02x?}", bytes);
+        }
+    }
+
+    #[test]
+    fn encoding_decoded_opcode_gives_its_bytes_back() {
+        for op in 0..=255u8 {
+            for &operand in &WORDS {
+                let bytes = [op, operand as u8, (operand >> 8) as u8];
+                let instruction = match decode(&bytes, 0) {
+                    Ok((instruction, _)) => instruction,
+                    Err(_) => continue,
+                };
+                assert_eq!(instruction.encode().unwrap(), &bytes[..instruction.get_size() as usize], "{}", instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn variants_without_an_opcode_are_not_encoded() {
+        let invalid = vec!(Instruction::MOV(Register::M, Register::M), Instruction::LDAX(RegisterPair::HL),
+            Instruction::LDAX(RegisterPair::SP), Instruction::STAX(RegisterPair::HL), Instruction::STAX(RegisterPair::SP),
+            Instruction::PUSH(RegisterPair::SP), Instruction::POP(RegisterPair::SP), Instruction::RST(8), Instruction::RST(0xff));
+        for instruction in invalid {
+            assert_eq!(instruction.encode(), Err(EncodeError(instruction)), "{}", instruction);
+        }
+        assert_eq!(EncodeError(Instruction::RST(9)).to_string(), "RST 9 has no 8080 opcode");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn undefined_opcodes_fault() {
+        let (mut cpu, _) = cpu_from_assembly("MVI A,1\nDB 0DDH\nHLT");
+        assert_eq!(cpu.try_step(&mut NoIo), Ok(Instruction::MVI(Register::A, 0x01)));
+        let fault = ExecutionError::UndefinedOpcode(DecodeError::UndefinedOpcode { addr: 0x0002, byte: 0xdd });
+        assert_eq!(cpu.try_step(&mut NoIo), Err(fault));
+        assert_eq!(fault.to_string(), "undefined opcode DDH at 0002H");
+        assert_eq!((cpu.get_pc(), cpu.get_cycles()), (0x0002, 7));
+        assert_eq!(cpu.try_step(&mut NoIo), Err(fault));
+    }
+}
@@ -0,0 +1,80 @@
+use super::{CPU, WrongInstructionType};
+use super::arithmetic_operations::sub_with_flags;
+use super::instruction::Instruction;
+use super::register::Register;
+
+pub fn execute_instruction(cpu : &mut CPU, instruction: Instruction) -> Result<(), WrongInstructionType> {
+    match instruction {
+        Instruction::ANA(reg) => {
+            let val = cpu.read_register(reg);
+            execute_logic(cpu, val, and)
+        },
+        Instruction::ANI(val) => execute_logic(cpu, val, and),
+        Instruction::ORA(reg) => {
+            let val = cpu.read_register(reg);
+            execute_logic(cpu, val, |a, b| (a | b, false))
+        },
+        Instruction::ORI(val) => execute_logic(cpu, val, |a, b| (a | b, false)),
+        Instruction::XRA(reg) => {
+            let val = cpu.read_register(reg);
+            execute_logic(cpu, val, |a, b| (a ^ b, false))
+        },
+        Instruction::XRI(val) => execute_logic(cpu, val, |a, b| (a ^ b, false)),
+        Instruction::CMP(reg) => {
+            let val = cpu.read_register(reg);
+            execute_compare(cpu, val)
+        },
+        Instruction::CPI(val) => execute_compare(cpu, val),
+        Instruction::RLC => execute_rotate(cpu, |a, _| (a.rotate_left(1), a & 0x80 != 0)),
+        Instruction::RRC => execute_rotate(cpu, |a, _| (a.rotate_right(1), a & 0x01 != 0)),
+        Instruction::RAL => execute_rotate(cpu, |a, carry| (a << 1 | carry as u8, a & 0x80 != 0)),
+        Instruction::RAR => execute_rotate(cpu, |a, carry| (a >> 1 | (carry as u8) << 7, a & 0x01 != 0)),
+        Instruction::CMA => {
+            let a = cpu.get_register(&Register::A);
+            cpu.set_register(Register::A, !a);
+            Ok(())
+        },
+        Instruction::CMC => {
+            cpu.flags.cy = !cpu.flags.cy;
+            Ok(())
+        },
+        Instruction::STC => {
+            cpu.flags.cy = true;
+            Ok(())
+        },
+        _ => Err(WrongInstructionType)
+    }
+}
+
+//On the 8080, AND sets auxiliary carry from bit 3 of either operand
+fn and(a: u8, b: u8) -> (u8, bool) {
+    (a & b, (a | b) & 0x08 != 0)
+}
+
+//Logical operations always clear carry
+fn execute_logic<F>(cpu: &mut CPU, val: u8, operation: F) -> Result<(), WrongInstructionType>
+    where F: Fn(u8, u8) -> (u8, bool)
+{
+    let (result, aux_carry) = operation(cpu.get_register(&Register::A), val);
+    cpu.flags.cy = false;
+    cpu.flags.ac = aux_carry;
+    cpu.flags.set_zsp(result);
+    cpu.set_register(Register::A, result);
+    Ok(())
+}
+
+fn execute_compare(cpu: &mut CPU, val: u8) -> Result<(), WrongInstructionType> {
+    let a = cpu.get_register(&Register::A);
+    sub_with_flags(cpu, a, val, false);
+    Ok(())
+}
+
+//Rotates only touch carry
+fn execute_rotate<F>(cpu: &mut CPU, operation: F) -> Result<(), WrongInstructionType>
+    where F: Fn(u8, bool) -> (u8, bool)
+{
+    let (result, carry) = operation(cpu.get_register(&Register::A), cpu.flags.cy);
+    cpu.flags.cy = carry;
+    cpu.set_register(Register::A, result);
+    Ok(())
+}
@@ -1,9 +1,11 @@
-use std::convert::From;
+use core::convert::From;
+use core::fmt;
 
 pub type RegisterOp = u8;
 pub type RegisterPairOp = u8;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Register {
     A,
     B,
@@ -17,7 +19,7 @@ pub enum Register {
 
 impl From<RegisterOp> for Register {
     fn from(op: RegisterOp) -> Self {
-        match op {
+        match op & 0b111 {
             0b000 => Register::B,
             0b001 => Register::C,
             0b010 => Register::D,
@@ -25,13 +27,13 @@ impl From<RegisterOp> for Register {
             0b100 => Register::H,
             0b101 => Register::L,
             0b110 => Register::M,
-            0b111 => Register::A,
-            _ => unreachable!(),
+            _ => Register::A,
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum RegisterPair {
     BC,
     DE,
@@ -41,12 +43,11 @@ pub enum RegisterPair {
 
 impl From<RegisterPairOp> for RegisterPair {
     fn from(op: RegisterPairOp) -> Self {
-        match op {
+        match op & 0b11 {
             0b00 => RegisterPair::BC,
             0b01 => RegisterPair::DE,
             0b10 => RegisterPair::HL,
-            0b11 => RegisterPair::SP,
-            _ => panic!("Invalid register pair requested: {}", op)
+            _ => RegisterPair::SP,
         }
     }
 }
@@ -75,4 +76,33 @@ impl From<RegisterPair> for RegisterPairOp {
             RegisterPair::SP => 0b11
         }
     }
-}
\ No newline at end of file
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Register::A => "A",
+            Register::B => "B",
+            Register::C => "C",
+            Register::D => "D",
+            Register::E => "E",
+            Register::H => "H",
+            Register::L => "L",
+            Register::M => "M",
+        };
+        f.write_str(name)
+    }
+}
+
+//Intel syntax names a pair after its high register, so BC is written as B
+impl fmt::Display for RegisterPair {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RegisterPair::BC => "B",
+            RegisterPair::DE => "D",
+            RegisterPair::HL => "H",
+            RegisterPair::SP => "SP",
+        };
+        f.write_str(name)
+    }
+}
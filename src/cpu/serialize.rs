@@ -0,0 +1,70 @@
+use alloc::vec::Vec;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{CPU, State, MEMORY_SIZE};
+
+//A CPU serializes as what a snapshot holds: its state, how much of
+//memory the loaded program took up and all 64K of memory
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    state: State,
+    rom_size: usize,
+    memory: &'a [u8],
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    state: State,
+    rom_size: usize,
+    memory: Vec<u8>,
+}
+
+impl Serialize for CPU {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SnapshotRef { state: self.get_state(), rom_size: self.rom_size, memory: &self.memory }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for CPU {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CPU, D::Error> {
+        let snapshot = Snapshot::deserialize(deserializer)?;
+        if snapshot.memory.len() != MEMORY_SIZE {
+            return Err(de::Error::invalid_length(snapshot.memory.len(), &"64K of memory"));
+        }
+        let mut cpu = CPU::empty();
+        cpu.memory = snapshot.memory;
+        cpu.rom_size = snapshot.rom_size.min(MEMORY_SIZE);
+        cpu.set_state(&snapshot.state);
+        Ok(cpu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::{CPU, NoIo};
+    use testing::cpu_from_assembly;
+
+    #[test]
+    fn serializes_through_serde() {
+        extern crate serde_json;
+
+        use cpu::instruction::Instruction;
+        use cpu::condition::ConditionOp;
+
+        let (mut cpu, _) = cpu_from_assembly("MVI A,42H\nSTC\nHLT");
+        for _ in 0..3 {
+            cpu.try_step(&mut NoIo).unwrap();
+        }
+        let json = serde_json::to_string(&cpu).unwrap();
+        let restored: CPU = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.get_state(), cpu.get_state());
+        assert_eq!(restored.read_memory(1), 0x42);
+        assert!(serde_json::from_str::<CPU>(r#"{"state":null,"rom_size":0,"memory":[]}"#).is_err());
+
+        let instruction = Instruction::CCOND(ConditionOp::NZ, 0x1234);
+        let json = serde_json::to_string(&instruction).unwrap();
+        assert_eq!(json, r#"{"CCOND":["NZ",4660]}"#);
+        assert_eq!(serde_json::from_str::<Instruction>(&json).unwrap(), instruction);
+    }
+}
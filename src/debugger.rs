@@ -0,0 +1,811 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use cpu::{CPU, Access, Address, Io, Port, State};
+use cpu::instruction::{Instruction, format_byte};
+use cpu::register::{Register, RegisterPair};
+use trace::write_trace_line;
+use error::LoadError;
+
+const LIST_BEFORE: usize = 4;
+const LIST_LENGTH: usize = 10;
+const DUMP_BYTES_PER_LINE: usize = 16;
+const DUMP_LENGTH: usize = 64;
+//How many instructions back the monitor can go
+const HISTORY_LENGTH: usize = 10000;
+
+const HELP: &str = "\
+step [n]              execute n instructions (s)
+next                  step over a call or restart (n)
+continue              run to a breakpoint or HLT (c)
+back [n]              undo the last n instructions (bs)
+rcontinue             run backwards to a breakpoint or watchpoint (rc)
+break [addr]          set a breakpoint, or list them all (b)
+delete addr           clear a breakpoint (d)
+watch [r|w|rw] addr [end]  stop on reads and/or writes to an address range, or list watchpoints (w)
+unwatch addr          clear the watchpoints starting at an address
+portbreak [in|out] port    stop on IN and/or OUT to a port, or list port breakpoints (pb)
+portdelete [in|out] port   clear a port breakpoint (pd)
+regs                  show registers and flags (r)
+set name value        change A-L, M, BC, DE, HL, SP, PC or a flag (S, Z, AC, P, CY)
+dump [addr] [length]  hex dump memory (x)
+edit addr byte...     write bytes into memory (e)
+list [addr] [count]   disassemble around the PC or from an address (l)
+in port value         set the value IN reads from a port
+trace file|off        log every instruction run to a file, or stop logging
+save file             write a snapshot of the CPU and memory
+restore file          go back to a saved snapshot
+quit                  leave the monitor (q)
+Numbers are hex, with or without a trailing H. Addresses can also be labels.";
+
+//IN reads whatever was last set for the port with the `in` command; OUT is reported as it happens
+struct Ports {
+    inputs: [u8; 256],
+    outputs: Vec<(Port, u8)>,
+}
+
+impl Io for Ports {
+    fn input(&mut self, port: Port) -> u8 {
+        self.inputs[port as usize]
+    }
+
+    fn output(&mut self, port: Port, value: u8) {
+        self.outputs.push((port, value));
+    }
+}
+
+//Stops on data reads and/or writes anywhere from `start` to `end` inclusive
+struct Watchpoint {
+    start: Address,
+    end: Address,
+    read: bool,
+    write: bool,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> bool {
+        match *access {
+            Access::Read { addr, .. } => self.read && self.start <= addr && addr <= self.end,
+            Access::Write { addr, .. } => self.write && self.start <= addr && addr <= self.end,
+            _ => false,
+        }
+    }
+}
+
+//Enough to undo one instruction: the CPU as it was beforehand and every memory
+//access the instruction made, writes carrying the value they replaced
+struct HistoryEntry {
+    state: State,
+    accesses: Vec<Access>,
+}
+
+enum Command {
+    Step(usize),
+    Next,
+    Continue,
+    Back(usize),
+    ReverseContinue,
+    Break(Option<Address>),
+    Delete(Address),
+    Watch(Option<Watchpoint>),
+    Unwatch(Address),
+    PortBreak(Option<(bool, bool, Port)>),
+    PortDelete(bool, bool, Port),
+    Regs,
+    Set(String, u16),
+    Dump(Option<Address>, usize),
+    Edit(Address, Vec<u8>),
+    List(Option<Address>, usize),
+    In(Port, u8),
+    Trace(Option<String>),
+    Save(String),
+    Restore(String),
+    Help,
+    Quit,
+}
+
+pub struct Debugger {
+    cpu: CPU,
+    ports: Ports,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: Vec<Watchpoint>,
+    input_breaks: BTreeSet<Port>,
+    output_breaks: BTreeSet<Port>,
+    symbols: BTreeMap<String, Address>,
+    labels: BTreeMap<Address, String>,
+    dump_addr: Address,
+    trace: Option<BufWriter<File>>,
+    history: VecDeque<HistoryEntry>,
+}
+
+impl Debugger {
+    pub fn new(cpu: CPU, symbols: BTreeMap<String, Address>) -> Debugger {
+        let labels = symbols.iter().map(|(name, addr)| (*addr, name.clone())).collect();
+        Debugger {
+            cpu,
+            ports: Ports { inputs: [0; 256], outputs: vec!() },
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec!(),
+            input_breaks: BTreeSet::new(),
+            output_breaks: BTreeSet::new(),
+            symbols,
+            labels,
+            dump_addr: 0,
+            trace: None,
+            history: VecDeque::new(),
+        }
+    }
+
+    //Reads commands until quit or the end of input
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: &mut W) -> io::Result<()> {
+        writeln!(out, "8080 monitor, type help for a list of commands.")?;
+        self.show_position(out)?;
+        write!(out, "> ")?;
+        out.flush()?;
+        for line in input.lines() {
+            let line = line?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if !words.is_empty() {
+                match self.parse_command(&words) {
+                    Ok(Command::Quit) => return Ok(()),
+                    Ok(command) => self.perform(command, out)?,
+                    Err(message) => writeln!(out, "{}", message)?,
+                }
+            }
+            write!(out, "> ")?;
+            out.flush()?;
+        }
+        writeln!(out)
+    }
+
+    fn parse_command(&self, words: &[&str]) -> Result<Command, String> {
+        let args = &words[1..];
+        let command = match words[0].to_ascii_lowercase().as_str() {
+            "step" | "s" => Command::Step(match args.first() {
+                Some(count) => parse_number(count)? as usize,
+                None => 1,
+            }),
+            "next" | "n" => Command::Next,
+            "continue" | "c" => Command::Continue,
+            "back" | "bs" => Command::Back(match args.first() {
+                Some(count) => parse_number(count)? as usize,
+                None => 1,
+            }),
+            "rcontinue" | "rc" => Command::ReverseContinue,
+            "break" | "b" => Command::Break(match args.first() {
+                Some(addr) => Some(self.parse_address(addr)?),
+                None => None,
+            }),
+            "delete" | "d" => Command::Delete(self.parse_address(args.first().ok_or("delete needs an address")?)?),
+            "watch" | "w" => {
+                if args.is_empty() {
+                    return Ok(Command::Watch(None));
+                }
+                let (read, write, rest) = match args[0].to_ascii_lowercase().as_str() {
+                    "r" => (true, false, &args[1..]),
+                    "w" => (false, true, &args[1..]),
+                    "rw" => (true, true, &args[1..]),
+                    _ => (true, true, args),
+                };
+                let start = self.parse_address(rest.first().ok_or("watch needs an address")?)?;
+                let end = match rest.get(1) {
+                    Some(end) => self.parse_address(end)?,
+                    None => start,
+                };
+                if end < start {
+                    return Err("a watchpoint can't end before it starts".to_string());
+                }
+                Command::Watch(Some(Watchpoint { start, end, read, write }))
+            },
+            "unwatch" => Command::Unwatch(self.parse_address(args.first().ok_or("unwatch needs an address")?)?),
+            "portbreak" | "pb" => {
+                if args.is_empty() {
+                    return Ok(Command::PortBreak(None));
+                }
+                let (input, output, port) = parse_port_args(args)?;
+                Command::PortBreak(Some((input, output, port)))
+            },
+            "portdelete" | "pd" => {
+                let (input, output, port) = parse_port_args(args)?;
+                Command::PortDelete(input, output, port)
+            },
+            "regs" | "r" => Command::Regs,
+            "set" => {
+                if args.len() != 2 {
+                    return Err("set needs a register or flag and a value".to_string());
+                }
+                let name = args[0].to_ascii_uppercase();
+                let value = parse_number(args[1])?;
+                let limit = match name.as_str() {
+                    "A" | "B" | "C" | "D" | "E" | "H" | "L" | "M" => 0xff,
+                    "BC" | "DE" | "HL" | "SP" | "PC" => 0xffff,
+                    "S" | "Z" | "AC" | "P" | "CY" => 1,
+                    _ => return Err(format!("there is no register or flag called {}", args[0])),
+                };
+                if value > limit {
+                    return Err(format!("{} only holds values up to {:X}H", name, limit));
+                }
+                Command::Set(name, value)
+            },
+            "dump" | "x" => Command::Dump(
+                match args.first() {
+                    Some(addr) => Some(self.parse_address(addr)?),
+                    None => None,
+                },
+                match args.get(1) {
+                    Some(length) => parse_number(length)? as usize,
+                    None => DUMP_LENGTH,
+                },
+            ),
+            "edit" | "e" => {
+                let addr = self.parse_address(args.first().ok_or("edit needs an address and some bytes")?)?;
+                let mut bytes = vec!();
+                for byte in &args[1..] {
+                    bytes.push(parse_byte(byte)?);
+                }
+                if bytes.is_empty() {
+                    return Err("edit needs an address and some bytes".to_string());
+                }
+                Command::Edit(addr, bytes)
+            },
+            "list" | "l" => Command::List(
+                match args.first() {
+                    Some(addr) => Some(self.parse_address(addr)?),
+                    None => None,
+                },
+                match args.get(1) {
+                    Some(count) => parse_number(count)? as usize,
+                    None => LIST_LENGTH,
+                },
+            ),
+            "in" => {
+                if args.len() != 2 {
+                    return Err("in needs a port and a value".to_string());
+                }
+                Command::In(parse_byte(args[0])?, parse_byte(args[1])?)
+            },
+            "trace" => match args.first() {
+                Some(word) if word.eq_ignore_ascii_case("off") => Command::Trace(None),
+                Some(path) => Command::Trace(Some(path.to_string())),
+                None => return Err("trace needs a file name or off".to_string()),
+            },
+            "save" => Command::Save(args.first().ok_or("save needs a file name")?.to_string()),
+            "restore" => Command::Restore(args.first().ok_or("restore needs a file name")?.to_string()),
+            "help" | "h" | "?" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            other => return Err(format!("unknown command {}, type help for a list", other)),
+        };
+        Ok(command)
+    }
+
+    fn parse_address(&self, word: &str) -> Result<Address, String> {
+        match self.symbols.get(&word.to_ascii_uppercase()) {
+            Some(addr) => Ok(*addr),
+            None => parse_number(word).map_err(|_| format!("{} is neither an address nor a label", word)),
+        }
+    }
+
+    fn perform<W: Write>(&mut self, command: Command, out: &mut W) -> io::Result<()> {
+        match command {
+            Command::Step(count) => self.run_until(out, None, Some(count.max(1))),
+            Command::Next => {
+                let pc = self.cpu.get_pc();
+                match self.cpu.instruction_at(pc) {
+                    Ok(instruction @ Instruction::CALL(_)) | Ok(instruction @ Instruction::CCOND(_, _)) | Ok(instruction @ Instruction::RST(_)) =>
+                        self.run_until(out, Some(pc.wrapping_add(instruction.get_size())), None),
+                    _ => self.run_until(out, None, Some(1)),
+                }
+            },
+            Command::Continue => self.run_until(out, None, None),
+            Command::Back(count) => self.run_back(out, Some(count.max(1))),
+            Command::ReverseContinue => self.run_back(out, None),
+            Command::Break(Some(addr)) => {
+                self.breakpoints.insert(addr);
+                writeln!(out, "Breakpoint set at {}", self.describe(addr))
+            },
+            Command::Break(None) => {
+                if self.breakpoints.is_empty() {
+                    return writeln!(out, "No breakpoints.");
+                }
+                for addr in &self.breakpoints {
+                    writeln!(out, "{}", self.describe(*addr))?;
+                }
+                Ok(())
+            },
+            Command::Delete(addr) => {
+                if self.breakpoints.remove(&addr) {
+                    writeln!(out, "Breakpoint at {} cleared", self.describe(addr))
+                } else {
+                    writeln!(out, "There is no breakpoint at {}", self.describe(addr))
+                }
+            },
+            Command::Watch(Some(watchpoint)) => {
+                let text = self.describe_watchpoint(&watchpoint);
+                self.watchpoints.push(watchpoint);
+                writeln!(out, "Watchpoint set on {}", text)
+            },
+            Command::Watch(None) => {
+                if self.watchpoints.is_empty() {
+                    return writeln!(out, "No watchpoints.");
+                }
+                for watchpoint in &self.watchpoints {
+                    writeln!(out, "{}", self.describe_watchpoint(watchpoint))?;
+                }
+                Ok(())
+            },
+            Command::Unwatch(addr) => {
+                let before = self.watchpoints.len();
+                self.watchpoints.retain(|watchpoint| watchpoint.start != addr);
+                if self.watchpoints.len() < before {
+                    writeln!(out, "Watchpoints at {} cleared", self.describe(addr))
+                } else {
+                    writeln!(out, "There is no watchpoint at {}", self.describe(addr))
+                }
+            },
+            Command::PortBreak(Some((input, output, port))) => {
+                if input {
+                    self.input_breaks.insert(port);
+                }
+                if output {
+                    self.output_breaks.insert(port);
+                }
+                writeln!(out, "Port breakpoint set on {} {:02X}H", direction(input, output), port)
+            },
+            Command::PortBreak(None) => {
+                if self.input_breaks.is_empty() && self.output_breaks.is_empty() {
+                    return writeln!(out, "No port breakpoints.");
+                }
+                for port in &self.input_breaks {
+                    writeln!(out, "IN {:02X}H", port)?;
+                }
+                for port in &self.output_breaks {
+                    writeln!(out, "OUT {:02X}H", port)?;
+                }
+                Ok(())
+            },
+            Command::PortDelete(input, output, port) => {
+                let removed_input = input && self.input_breaks.remove(&port);
+                let removed_output = output && self.output_breaks.remove(&port);
+                if removed_input || removed_output {
+                    writeln!(out, "Port breakpoint on {} {:02X}H cleared", direction(removed_input, removed_output), port)
+                } else {
+                    writeln!(out, "There is no port breakpoint on {} {:02X}H", direction(input, output), port)
+                }
+            },
+            Command::Regs => self.show_position(out),
+            //Undoing past a change made by hand would only undo part of it, so history starts over
+            Command::Set(name, value) => {
+                self.history.clear();
+                self.set(&name, value);
+                self.show_registers(out)
+            },
+            Command::Dump(addr, length) => {
+                let start = addr.unwrap_or(self.dump_addr);
+                self.dump(out, start, length)?;
+                self.dump_addr = start.wrapping_add(length as u16);
+                Ok(())
+            },
+            Command::Edit(addr, bytes) => {
+                self.history.clear();
+                for (offset, byte) in bytes.iter().enumerate() {
+                    self.cpu.write_memory(addr.wrapping_add(offset as u16), *byte);
+                }
+                self.dump(out, addr, bytes.len())
+            },
+            Command::List(Some(addr), count) => self.list(out, addr, count),
+            Command::List(None, count) => {
+                let pc = self.cpu.get_pc();
+                let start = self.list_start(pc);
+                self.list(out, start, count)
+            },
+            Command::In(port, value) => {
+                self.ports.inputs[port as usize] = value;
+                Ok(())
+            },
+            Command::Trace(Some(path)) => {
+                match File::create(&path) {
+                    Ok(file) => {
+                        self.trace = Some(BufWriter::new(file));
+                        writeln!(out, "Tracing to {}", path)
+                    },
+                    Err(error) => writeln!(out, "Unable to trace to {}: {}", path, error),
+                }
+            },
+            Command::Trace(None) => {
+                match self.trace.take() {
+                    Some(mut trace) => {
+                        trace.flush()?;
+                        writeln!(out, "Tracing stopped")
+                    },
+                    None => writeln!(out, "Not tracing"),
+                }
+            },
+            Command::Save(path) => {
+                let result = File::create(&path).and_then(|file| {
+                    let mut file = BufWriter::new(file);
+                    self.cpu.write_snapshot(&mut file)?;
+                    file.flush()
+                });
+                match result {
+                    Ok(()) => writeln!(out, "Saved a snapshot to {}", path),
+                    Err(error) => writeln!(out, "Unable to save to {}: {}", path, error),
+                }
+            },
+            Command::Restore(path) => {
+                match File::open(&path).map_err(LoadError::from).and_then(|file| CPU::read_snapshot(&mut BufReader::new(file))) {
+                    Ok(cpu) => {
+                        self.cpu = cpu;
+                        self.history.clear();
+                        writeln!(out, "Restored the snapshot in {}", path)?;
+                        self.show_position(out)
+                    },
+                    Err(error) => writeln!(out, "Unable to restore {}: {}", path, error),
+                }
+            },
+            Command::Help => writeln!(out, "{}", HELP),
+            Command::Quit => Ok(()),
+        }
+    }
+
+    fn set(&mut self, name: &str, value: u16) {
+        let mut flags = self.cpu.get_flags();
+        let set = value != 0;
+        match name {
+            "A" => self.cpu.set_register(Register::A, value as u8),
+            "B" => self.cpu.set_register(Register::B, value as u8),
+            "C" => self.cpu.set_register(Register::C, value as u8),
+            "D" => self.cpu.set_register(Register::D, value as u8),
+            "E" => self.cpu.set_register(Register::E, value as u8),
+            "H" => self.cpu.set_register(Register::H, value as u8),
+            "L" => self.cpu.set_register(Register::L, value as u8),
+            "M" => self.cpu.set_register(Register::M, value as u8),
+            "BC" => self.cpu.set_register_pair(RegisterPair::BC, value),
+            "DE" => self.cpu.set_register_pair(RegisterPair::DE, value),
+            "HL" => self.cpu.set_register_pair(RegisterPair::HL, value),
+            "SP" => self.cpu.set_register_pair(RegisterPair::SP, value),
+            "PC" => self.cpu.set_pc(value),
+            "S" => flags.s = set,
+            "Z" => flags.z = set,
+            "AC" => flags.ac = set,
+            "P" => flags.p = set,
+            _ => flags.cy = set,
+        }
+        self.cpu.set_flags(flags);
+    }
+
+    //Runs until the PC reaches `stop_at`, `limit` instructions have run, a breakpoint,
+    //watchpoint or port breakpoint is hit, the CPU halts or meets an opcode it can't run;
+    //the instruction under the PC always gets to run first.
+    fn run_until<W: Write>(&mut self, out: &mut W, stop_at: Option<Address>, limit: Option<usize>) -> io::Result<()> {
+        let mut count = 0;
+        loop {
+            if let Some(ref mut trace) = self.trace {
+                write_trace_line(&self.cpu, trace)?;
+            }
+            let state = self.cpu.get_state();
+            if let Err(error) = self.cpu.try_step(&mut self.ports) {
+                writeln!(out, "Stopped: {}", error)?;
+                break;
+            }
+            count += 1;
+            if self.history.len() == HISTORY_LENGTH {
+                self.history.pop_front();
+            }
+            self.history.push_back(HistoryEntry { state, accesses: self.cpu.get_accesses().to_vec() });
+            for (port, value) in self.ports.outputs.drain(..) {
+                writeln!(out, "OUT {:02X}H: {:02X}H", port, value)?;
+            }
+            if let Some(message) = self.triggered(self.cpu.get_accesses()) {
+                writeln!(out, "{}", message)?;
+                break;
+            }
+            let pc = self.cpu.get_pc();
+            if self.cpu.is_halted() {
+                writeln!(out, "Halted.")?;
+                break;
+            }
+            if self.breakpoints.contains(&pc) {
+                writeln!(out, "Breakpoint at {}", self.describe(pc))?;
+                break;
+            }
+            if Some(pc) == stop_at || Some(count) == limit {
+                break;
+            }
+        }
+        self.show_position(out)
+    }
+
+    //Undoes instructions until `limit` of them have been undone, the history runs out, or a
+    //breakpoint is reached or a watchpoint or port breakpoint undone, so that going backwards
+    //stops just before the instruction that would have stopped going forwards
+    fn run_back<W: Write>(&mut self, out: &mut W, limit: Option<usize>) -> io::Result<()> {
+        let mut count = 0;
+        loop {
+            let entry = match self.history.pop_back() {
+                Some(entry) => entry,
+                None => {
+                    writeln!(out, "Reached the start of the recorded history.")?;
+                    break;
+                },
+            };
+            for access in entry.accesses.iter().rev() {
+                if let Access::Write { addr, old, .. } = *access {
+                    self.cpu.write_memory(addr, old);
+                }
+            }
+            self.cpu.set_state(&entry.state);
+            count += 1;
+            if let Some(message) = self.triggered(&entry.accesses) {
+                writeln!(out, "{}", message)?;
+                break;
+            }
+            let pc = self.cpu.get_pc();
+            if self.breakpoints.contains(&pc) {
+                writeln!(out, "Breakpoint at {}", self.describe(pc))?;
+                break;
+            }
+            if Some(count) == limit {
+                break;
+            }
+        }
+        self.show_position(out)
+    }
+
+    //Describes the first of an instruction's accesses that a watchpoint or port breakpoint stops on
+    fn triggered(&self, accesses: &[Access]) -> Option<String> {
+        for access in accesses {
+            let message = match *access {
+                Access::Read { addr, value } if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(access)) =>
+                    format!("Watchpoint: read {:02X}H from {}", value, self.describe(addr)),
+                Access::Write { addr, old, new } if self.watchpoints.iter().any(|watchpoint| watchpoint.matches(access)) =>
+                    format!("Watchpoint: write to {} changed {:02X}H to {:02X}H", self.describe(addr), old, new),
+                Access::Input { port, value } if self.input_breaks.contains(&port) =>
+                    format!("Port breakpoint: IN {:02X}H read {:02X}H", port, value),
+                Access::Output { port, value } if self.output_breaks.contains(&port) =>
+                    format!("Port breakpoint: OUT {:02X}H wrote {:02X}H", port, value),
+                _ => continue,
+            };
+            return Some(message);
+        }
+        None
+    }
+
+    fn show_registers<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let reg = |reg| self.cpu.get_register(&reg);
+        let pair = |pair| self.cpu.get_register_pair(pair);
+        writeln!(out, "A={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} M={:02X}  BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X}",
+            reg(Register::A), reg(Register::B), reg(Register::C), reg(Register::D), reg(Register::E), reg(Register::H), reg(Register::L),
+            reg(Register::M), pair(RegisterPair::BC), pair(RegisterPair::DE), pair(RegisterPair::HL), pair(RegisterPair::SP),
+            self.cpu.get_pc())?;
+        let flags = self.cpu.get_flags();
+        writeln!(out, "S={} Z={} AC={} P={} CY={}  interrupts {}  {} cycles",
+            flags.s as u8, flags.z as u8, flags.ac as u8, flags.p as u8, flags.cy as u8,
+            if self.cpu.interrupts_enabled() { "enabled" } else { "disabled" }, self.cpu.get_cycles())
+    }
+
+    fn show_position<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        self.show_registers(out)?;
+        let pc = self.cpu.get_pc();
+        self.list(out, pc, 1)
+    }
+
+    fn list<W: Write>(&mut self, out: &mut W, start: Address, count: usize) -> io::Result<()> {
+        let pc = self.cpu.get_pc();
+        let mut addr = start;
+        for _ in 0..count {
+            //a byte the CPU can't run is listed as data, as the disassembler lists it
+            let (instruction, size) = match self.cpu.instruction_at(addr) {
+                Ok(instruction) => (Some(instruction), instruction.get_size()),
+                Err(_) => (None, 1),
+            };
+            if let Some(label) = self.labels.get(&addr) {
+                writeln!(out, "{}:", label)?;
+            }
+            let bytes: Vec<String> = (0..size).map(|offset| format!("{:02X}", self.cpu.read_memory(addr.wrapping_add(offset)))).collect();
+            let marker = if addr == pc { ">" } else { " " };
+            let target = match instruction {
+                Some(Instruction::JMP(target)) | Some(Instruction::JCOND(_, target)) | Some(Instruction::CALL(target)) |
+                Some(Instruction::CCOND(_, target)) | Some(Instruction::LDA(target)) | Some(Instruction::STA(target)) |
+                Some(Instruction::LHLD(target)) | Some(Instruction::SHLD(target)) => self.labels.get(&target),
+                Some(Instruction::LXI(_, (hi, lo))) => self.labels.get(&((hi as u16) << 8 | lo as u16)),
+                _ => None,
+            };
+            let text = match instruction {
+                Some(instruction) => instruction.to_string(),
+                None => format!("DB {}", format_byte(self.cpu.read_memory(addr))),
+            };
+            match target {
+                Some(label) => writeln!(out, "{} {:04X}  {:<9} {:<16}; {}", marker, addr, bytes.join(" "), text, label)?,
+                None => writeln!(out, "{} {:04X}  {:<9} {}", marker, addr, bytes.join(" "), text)?,
+            }
+            addr = addr.wrapping_add(size);
+        }
+        Ok(())
+    }
+
+    //Code can't be decoded backwards, so decode forwards from the furthest point a little
+    //way back that still lands straight on the PC, and keep the last few instructions.
+    fn list_start(&mut self, pc: Address) -> Address {
+        for back in (1..=LIST_BEFORE * 6).rev() {
+            if back > pc as usize {
+                continue;
+            }
+            let mut starts = vec!();
+            let mut addr = pc as usize - back;
+            while addr < pc as usize {
+                starts.push(addr as Address);
+                addr += self.cpu.instruction_at(addr as Address).map_or(1, |instruction| instruction.get_size()) as usize;
+            }
+            if addr == pc as usize {
+                return starts[starts.len().saturating_sub(LIST_BEFORE)];
+            }
+        }
+        pc
+    }
+
+    fn dump<W: Write>(&self, out: &mut W, start: Address, length: usize) -> io::Result<()> {
+        let mut offset = 0;
+        while offset < length {
+            let line_addr = start.wrapping_add(offset as u16);
+            let count = DUMP_BYTES_PER_LINE.min(length - offset);
+            let bytes: Vec<u8> = (0..count).map(|i| self.cpu.read_memory(line_addr.wrapping_add(i as u16))).collect();
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let text: String = bytes.iter().map(|&byte| if (0x20..0x7f).contains(&byte) { byte as char } else { '.' }).collect();
+            writeln!(out, "{:04X}  {:<48} {}", line_addr, hex.join(" "), text)?;
+            offset += count;
+        }
+        Ok(())
+    }
+
+    fn describe_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        let kind = match (watchpoint.read, watchpoint.write) {
+            (true, false) => "reads of",
+            (false, true) => "writes to",
+            _ => "reads of and writes to",
+        };
+        if watchpoint.start == watchpoint.end {
+            format!("{} {}", kind, self.describe(watchpoint.start))
+        } else {
+            format!("{} {} to {}", kind, self.describe(watchpoint.start), self.describe(watchpoint.end))
+        }
+    }
+
+    fn describe(&self, addr: Address) -> String {
+        match self.labels.get(&addr) {
+            Some(label) => format!("{:04X}H ({})", addr, label),
+            None => format!("{:04X}H", addr),
+        }
+    }
+}
+
+//Monitor convention: numbers are hex, and an Intel style trailing H is allowed
+fn parse_number(word: &str) -> Result<u16, String> {
+    let digits = word.strip_suffix(|c| c == 'h' || c == 'H').unwrap_or(word);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hex number", word))
+}
+
+//An optional in or out followed by a port; leaving out the direction means both
+fn parse_port_args(args: &[&str]) -> Result<(bool, bool, Port), String> {
+    let (input, output, rest) = match args.first().map(|word| word.to_ascii_lowercase()) {
+        Some(ref word) if word == "in" => (true, false, &args[1..]),
+        Some(ref word) if word == "out" => (false, true, &args[1..]),
+        _ => (true, true, args),
+    };
+    match rest.first() {
+        Some(port) => Ok((input, output, parse_byte(port)?)),
+        None => Err("a port breakpoint needs a port".to_string()),
+    }
+}
+
+fn direction(input: bool, output: bool) -> &'static str {
+    match (input, output) {
+        (true, false) => "IN",
+        (false, true) => "OUT",
+        _ => "IN/OUT",
+    }
+}
+
+fn parse_byte(word: &str) -> Result<u8, String> {
+    match parse_number(word)? {
+        value if value <= 0xff => Ok(value as u8),
+        _ => Err(format!("{} does not fit in a byte", word)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::cpu_from_assembly;
+
+    use super::Debugger;
+
+    #[test]
+    fn runs_to_breakpoints_and_edits_state() {
+        let (cpu, assembly) = cpu_from_assembly("\
+        ORG     0
+START:  LXI     SP,100H
+        MVI     A,38H
+        CALL    DOUBLE
+        OUT     1
+LOOP:   DCR     B
+        JNZ     LOOP
+        HLT
+DOUBLE: ADD     A
+        DAA
+        RET
+");
+        let mut debugger = Debugger::new(cpu, assembly.symbols);
+        let script = "break loop\nc\nn\ndelete loop\nset b 1\nc\nregs\nx 0 4\nfrob\n";
+        let mut out = vec!();
+        debugger.run(script.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("OUT 01H: 76H\nBreakpoint at 000AH (LOOP)\n"), "{}", out);
+        assert!(out.contains("> 000A  05        DCR B"), "{}", out);
+        assert!(out.contains("Halted.\nA=76 B=00"), "{}", out);
+        assert!(out.contains("0000  31 00 01 3E"), "{}", out);
+        assert!(out.contains("unknown command frob"), "{}", out);
+    }
+
+    #[test]
+    fn stops_on_watchpoints_and_port_breakpoints() {
+        let (cpu, assembly) = cpu_from_assembly("\
+        ORG     0
+START:  LXI     SP,100H
+        LXI     H,COUNT
+        INR     M
+        MOV     A,M
+        PUSH    PSW
+        IN      2
+        OUT     3
+        HLT
+COUNT:  DB      41H
+");
+        let mut debugger = Debugger::new(cpu, assembly.symbols);
+        let script = "watch w count\nwatch r count\nwatch w fe ff\npb in 2\npb out 3\nin 2 7\nc\nc\nc\nc\nc\nc\n";
+        let mut out = vec!();
+        debugger.run(script.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Watchpoint: read 41H from 000EH (COUNT)"), "{}", out);
+        assert!(out.contains("Watchpoint: read 42H from 000EH (COUNT)"), "{}", out);
+        assert!(out.contains("Watchpoint: write to 00FEH changed 00H to 06H"), "{}", out);
+        assert!(out.contains("Port breakpoint: IN 02H read 07H"), "{}", out);
+        assert!(out.contains("OUT 03H: 07H\nPort breakpoint: OUT 03H wrote 07H"), "{}", out);
+        assert!(out.contains("Halted."), "{}", out);
+    }
+
+    #[test]
+    fn steps_back_through_history() {
+        let (cpu, assembly) = cpu_from_assembly("\
+        ORG     0
+START:  LXI     H,COUNT
+        MVI     B,3
+LOOP:   INR     M
+        DCR     B
+        JNZ     LOOP
+        HLT
+COUNT:  DB      0
+");
+        let mut debugger = Debugger::new(cpu, assembly.symbols);
+        let script = "c\nwatch w count\nrc\nx count 1\nbs 2\nunwatch count\nrc\nbs\nc\n";
+        let mut out = vec!();
+        debugger.run(script.as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Watchpoint: write to 000BH (COUNT) changed 02H to 03H\nA=00 B=01"), "{}", out);
+        assert!(out.contains("000B  02"), "{}", out);
+        assert!(out.contains("A=00 B=02 C=00 D=00 E=00 H=00 L=0B M=02"), "{}", out);
+        assert!(out.contains("Reached the start of the recorded history.\nA=00 B=00 C=00 D=00 E=00 H=00 L=00 M=21"), "{}", out);
+        assert!(out.matches("Halted.\nA=00 B=00 C=00 D=00 E=00 H=00 L=0B M=03").count() == 2, "{}", out);
+    }
+
+    #[test]
+    fn stops_at_undefined_opcodes() {
+        let (cpu, assembly) = cpu_from_assembly("MVI A,1\nDB 0DDH\nHLT");
+        let mut debugger = Debugger::new(cpu, assembly.symbols);
+        let mut out = vec!();
+        debugger.run("c\nc\nl 0\n".as_bytes(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.matches("Stopped: undefined opcode DDH at 0002H\nA=01").count(), 2, "{}", out);
+        assert!(out.contains("> 0002  DD        DB 0DDH\n  0003  76        HLT\n"), "{}", out);
+        assert!(!out.contains("Halted."), "{}", out);
+    }
+}
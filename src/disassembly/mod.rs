@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+use cpu::{CPU, Address, MEMORY_SIZE};
+use cpu::decode::instructions;
+use cpu::instruction::{Instruction, Operand, format_byte};
+use error::DecodeError;
+
+mod source;
+
+//How each line of a disassembly looks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    //Address, bytes and Intel mnemonics, with labels where the symbols have them
+    Intel,
+    //Address and the decoded instruction as the emulator sees it
+    Debug,
+    //One JSON object per line, with the operands, timing and branch targets split out
+    Json,
+}
+
+//Disassembles `bytes`, found at `origin`, a line at a time. Bytes that aren't an instruction,
+//whether undefined or cut off by the end of the input, are written as data.
+pub fn write_disassembly<W: Write>(bytes: &[u8], origin: Address, syntax: Syntax, symbols: &BTreeMap<String, Address>, out: &mut W) -> io::Result<()> {
+    let labels: HashMap<Address, &String> = symbols.iter().map(|(name, addr)| (*addr, name)).collect();
+    for decoded in instructions(bytes, origin) {
+        let (addr, size, instruction) = match decoded {
+            Ok((addr, instruction)) => (addr, instruction.get_size() as usize, Some(instruction)),
+            Err(DecodeError::UndefinedOpcode { addr, .. }) => (addr, 1, None),
+            Err(DecodeError::Truncated { addr, .. }) => (addr, bytes.len() - addr.wrapping_sub(origin) as usize, None),
+        };
+        let offset = addr.wrapping_sub(origin) as usize;
+        let code = &bytes[offset..offset + size];
+        if syntax == Syntax::Json {
+            let error = decoded.err().map(|error| error.to_string());
+            write_json_line(out, addr, code, labels.get(&addr).map(|label| label.as_str()), instruction, error)?;
+            continue;
+        }
+        let hex: Vec<String> = code.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let data: Vec<String> = code.iter().map(|&byte| format_byte(byte)).collect();
+        if let Some(label) = labels.get(&addr).filter(|_| syntax == Syntax::Intel) {
+            writeln!(out, "{}:", label)?;
+        }
+        match (syntax, instruction) {
+            (Syntax::Intel, Some(instruction)) => writeln!(out, "{:04X}  {:<9} {}", addr, hex.join(" "), instruction)?,
+            (Syntax::Intel, None) => writeln!(out, "{:04X}  {:<9} DB {}", addr, hex.join(" "), data.join(","))?,
+            (Syntax::Debug, Some(instruction)) => writeln!(out, "{:#06x}    {:?}", addr, instruction)?,
+            (Syntax::Debug, None) => writeln!(out, "{:#06x}    DB({})", addr, data.join(", "))?,
+            (Syntax::Json, _) => {},
+        }
+    }
+    Ok(())
+}
+
+//Instructions have their mnemonic, operands, timing and targets; anything else has the error
+//that kept it from being one
+fn write_json_line<W: Write>(out: &mut W, addr: Address, code: &[u8], label: Option<&str>, instruction: Option<Instruction>, error: Option<String>) -> io::Result<()> {
+    let bytes: Vec<String> = code.iter().map(|byte| byte.to_string()).collect();
+    write!(out, "{{\"address\":{},\"bytes\":[{}],\"length\":{}", addr, bytes.join(","), code.len())?;
+    if let Some(label) = label {
+        write!(out, ",\"label\":{}", json_string(label))?;
+    }
+    if let Some(instruction) = instruction {
+        let operands: Vec<String> = instruction.operands().iter().map(|operand| {
+            let (kind, value) = match *operand {
+                Operand::Register(reg) => ("register", json_string(&reg.to_string())),
+                Operand::RegisterPair(pair) => ("register_pair", json_string(&pair.to_string())),
+                Operand::Psw => ("register_pair", json_string("PSW")),
+                Operand::Immediate(value) => ("immediate", value.to_string()),
+                Operand::Address(addr) => ("address", addr.to_string()),
+                Operand::Port(port) => ("port", port.to_string()),
+                Operand::Condition(cond) => ("condition", json_string(&cond.to_string())),
+            };
+            format!("{{\"type\":\"{}\",\"value\":{}}}", kind, value)
+        }).collect();
+        let targets: Vec<String> = instruction.branch_targets().iter().map(|target| target.to_string()).collect();
+        write!(out, ",\"mnemonic\":{},\"operands\":[{}],\"cycles\":{},\"cycles_taken\":{},\"targets\":[{}]",
+            json_string(&instruction.mnemonic()), operands.join(","), instruction.get_cycles(), instruction.get_cycles_taken(), targets.join(","))?;
+    }
+    if let Some(error) = error {
+        write!(out, ",\"error\":{}", json_string(&error))?;
+    }
+    writeln!(out, "}}")
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl CPU {
+    //Disassembles memory from `start` up to, but not including, `end`
+    pub fn write_disassembly<W: Write>(&self, start: Address, end: usize, syntax: Syntax, symbols: &BTreeMap<String, Address>, out: &mut W) -> io::Result<()> {
+        let end = end.clamp(start as usize, MEMORY_SIZE);
+        write_disassembly(&self.memory[start as usize..end], start, syntax, symbols, out)
+    }
+
+    //The instruction at every address a straight run through memory reaches, as the
+    //emulator decodes them
+    pub fn write_memory_disassembly<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.write_disassembly(0, MEMORY_SIZE, Syntax::Debug, &BTreeMap::new(), out)
+    }
+
+    //Re-assemblable source for the `len` bytes loaded at `origin`, to any writer
+    pub fn write_source<W: Write>(&self, origin: Address, len: usize, out: &mut W) -> io::Result<()> {
+        source::write_source(self, origin, len, out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::{self, Write};
+
+    use super::{write_disassembly, Syntax};
+
+    //Fails after taking a few bytes, like a pipe whose reader has gone away
+    struct BrokenPipe(usize);
+
+    impl Write for BrokenPipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 < buf.len() {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "reader has gone away"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn streams_listings_and_reports_write_errors() {
+        let bytes = [0x3e, 0x41, 0x08, 0xc3, 0x00, 0x01, 0xcd, 0x00];
+        let mut symbols = BTreeMap::new();
+        symbols.insert("LOOP".to_string(), 0x103);
+        let mut out = vec!();
+        write_disassembly(&bytes, 0x100, Syntax::Intel, &symbols, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "\
+0100  3E 41     MVI A,41H
+0102  08        DB 08H
+LOOP:
+0103  C3 00 01  JMP 0100H
+0106  CD 00     DB 0CDH,00H
+");
+        let mut out = vec!();
+        write_disassembly(&bytes[..3], 0x100, Syntax::Debug, &symbols, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0x0100    MVI(A, 65)\n0x0102    DB(08H)\n");
+
+        let mut out = vec!();
+        write_disassembly(&[0xcc, 0x34, 0x12, 0xf5, 0xdd], 0x103, Syntax::Json, &symbols, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), concat!(
+            r#"{"address":259,"bytes":[204,52,18],"length":3,"label":"LOOP","mnemonic":"CZ","operands":[{"type":"condition","value":"Z"},{"type":"address","value":4660}],"cycles":11,"cycles_taken":17,"targets":[4660]}"#, "\n",
+            r#"{"address":262,"bytes":[245],"length":1,"mnemonic":"PUSH","operands":[{"type":"register_pair","value":"PSW"}],"cycles":11,"cycles_taken":11,"targets":[]}"#, "\n",
+            r#"{"address":263,"bytes":[221],"length":1,"error":"undefined opcode DDH at 0107H"}"#, "\n"));
+
+        let error = write_disassembly(&bytes, 0x100, Syntax::Intel, &symbols, &mut BrokenPipe(30)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::BrokenPipe);
+    }
+}
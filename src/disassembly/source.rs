@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use cpu::{CPU, Address, MEMORY_SIZE};
+use cpu::decode::decode;
+use cpu::instruction::{Instruction, format_byte, format_word};
+
+const DATA_BYTES_PER_LINE: usize = 8;
+
+enum LineKind {
+    Code(Instruction),
+    Bytes,
+    Word,
+}
+
+struct Line {
+    addr: usize,
+    size: usize,
+    kind: LineKind,
+}
+
+//Writes the `len` bytes loaded at `origin` back out as Intel-syntax source that assembles to the
+//same bytes at the same place. Code is found by following control flow from the origin and any
+//restart vectors inside the program; anything never reached is emitted as DB/DW data so it
+//survives the round trip untouched.
+pub fn write_source<W: Write>(cpu: &CPU, origin: Address, len: usize, out: &mut W) -> io::Result<()> {
+    let origin = origin as usize;
+    let end = (origin + len).min(MEMORY_SIZE);
+    let mut code = find_code(cpu, origin, end);
+
+    let mut targets = BTreeSet::new();
+    let mut word_targets = BTreeSet::new();
+    for instruction in code.values() {
+        if let Some(target) = referenced_address(instruction) {
+            targets.insert(target as usize);
+            match instruction {
+                Instruction::LHLD(_) | Instruction::SHLD(_) => { word_targets.insert(target as usize); },
+                _ => {},
+            }
+        }
+    }
+
+    let mut lines = vec!();
+    let mut addr = origin;
+    while addr < end {
+        if let Some(instruction) = code.remove(&addr) {
+            let size = instruction.get_size() as usize;
+            lines.push(Line { addr, size, kind: LineKind::Code(instruction) });
+            addr += size;
+            continue;
+        }
+        let is_boundary = |a: usize| code.contains_key(&a) || targets.contains(&a);
+        if word_targets.contains(&addr) && addr + 1 < end && !is_boundary(addr + 1) {
+            lines.push(Line { addr, size: 2, kind: LineKind::Word });
+            addr += 2;
+            continue;
+        }
+        let start = addr;
+        addr += 1;
+        while addr < end && addr - start < DATA_BYTES_PER_LINE && !is_boundary(addr) {
+            addr += 1;
+        }
+        lines.push(Line { addr: start, size: addr - start, kind: LineKind::Bytes });
+    }
+
+    let mut labels = BTreeSet::new();
+    let mut equates = BTreeSet::new();
+    let mut symbols = BTreeMap::new();
+    for &target in &targets {
+        if (origin..end).contains(&target) {
+            //a target inside another line can't carry its own label, so point at it by offset
+            let line = &lines[lines.partition_point(|line| line.addr <= target) - 1];
+            labels.insert(line.addr);
+            if line.addr == target {
+                symbols.insert(target, label_name(target));
+            } else {
+                symbols.insert(target, format!("{}+{}", label_name(line.addr), target - line.addr));
+            }
+        } else {
+            equates.insert(target);
+            symbols.insert(target, equate_name(target));
+        }
+    }
+
+    writeln!(out, "; Disassembly of {} bytes", end - origin)?;
+    for &target in &equates {
+        writeln!(out, "{:<8}EQU     {}", equate_name(target), format_word(target as u16))?;
+    }
+    writeln!(out, "        ORG     {}", format_word(origin as u16))?;
+    for line in &lines {
+        let label = if labels.contains(&line.addr) {
+            format!("{}:", label_name(line.addr))
+        } else {
+            String::new()
+        };
+        let bytes = &cpu.memory[line.addr..line.addr + line.size];
+        let body = match line.kind {
+            LineKind::Code(ref instruction) => {
+                let text = instruction.to_string();
+                let (mnemonic, operands) = match text.split_once(' ') {
+                    Some((mnemonic, operands)) => (mnemonic.to_string(), operands.to_string()),
+                    None => (text.clone(), String::new()),
+                };
+                let operands = match referenced_address(instruction) {
+                    Some(target) => symbols[&(target as usize)].clone(),
+                    None => operands,
+                };
+                format!("{:<8}{}", mnemonic, operands)
+            },
+            LineKind::Word => {
+                let word = bytes[0] as u16 | (bytes[1] as u16) << 8;
+                format!("DW      {}", format_word(word))
+            },
+            LineKind::Bytes => {
+                let values: Vec<String> = bytes.iter().map(|b| format_byte(*b)).collect();
+                format!("DB      {}", values.join(","))
+            },
+        };
+        let raw: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        writeln!(out, "{:<8}{:<32}; {:04X}  {}", label, body, line.addr, raw.join(" "))?;
+    }
+    writeln!(out, "        END")?;
+    Ok(())
+}
+
+fn find_code(cpu: &CPU, origin: usize, end: usize) -> BTreeMap<usize, Instruction> {
+    let mut code = BTreeMap::new();
+    let mut pending: Vec<usize> = vec!(origin);
+    //restart vectors are entered by interrupts, which never show up as a jump in the ROM
+    pending.extend((0..8).map(|vector| vector * 8));
+    while let Some(addr) = pending.pop() {
+        if addr < origin || addr >= end || code.contains_key(&addr) {
+            continue;
+        }
+        let instruction = match decode_at(cpu, addr, end) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let next = addr + instruction.get_size() as usize;
+        match instruction {
+            Instruction::JMP(target) => pending.push(target as usize),
+            Instruction::JCOND(_, target) | Instruction::CALL(target) | Instruction::CCOND(_, target) => {
+                pending.push(target as usize);
+                pending.push(next);
+            },
+            Instruction::RST(vector) => {
+                pending.push(vector as usize * 8);
+                pending.push(next);
+            },
+            Instruction::RET | Instruction::PCHL => {},
+            _ => pending.push(next),
+        }
+        code.insert(addr, instruction);
+    }
+    code
+}
+
+//Undefined opcodes and instructions running past the end of the ROM stay data
+fn decode_at(cpu: &CPU, addr: usize, end: usize) -> Option<Instruction> {
+    decode(&cpu.memory[addr..end], addr as Address).ok().map(|(instruction, _)| instruction)
+}
+
+fn referenced_address(instruction: &Instruction) -> Option<Address> {
+    match instruction {
+        Instruction::JMP(addr) | Instruction::JCOND(_, addr) |
+        Instruction::CALL(addr) | Instruction::CCOND(_, addr) |
+        Instruction::LDA(addr) | Instruction::STA(addr) |
+        Instruction::LHLD(addr) | Instruction::SHLD(addr) => Some(*addr),
+        _ => None,
+    }
+}
+
+fn label_name(addr: usize) -> String {
+    format!("L{:04X}", addr)
+}
+
+fn equate_name(addr: usize) -> String {
+    format!("X{:04X}", addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use assembler;
+    use cpu::{CPU, Address};
+    use super::write_source;
+
+    fn round_trip(origin: Address, rom: Vec<u8>) {
+        let mut cpu = CPU::empty();
+        cpu.load(origin, &rom);
+        let mut source = vec!();
+        write_source(&cpu, origin, rom.len(), &mut source).unwrap();
+        let source = String::from_utf8(source).unwrap();
+        let assembly = match assembler::assemble(&source) {
+            Ok(assembly) => assembly,
+            Err(errors) => panic!("{}\n{}", errors[0], source),
+        };
+        assert_eq!(assembly.origin(), origin);
+        assert_eq!(assembly.to_binary(), rom);
+    }
+
+    #[test]
+    fn reassembles_every_opcode() {
+        let mut rom = vec!();
+        for op in 0..=255u8 {
+            rom.extend_from_slice(&[op, op.wrapping_mul(7), 0x40]);
+        }
+        round_trip(0, rom);
+    }
+
+    #[test]
+    fn reassembles_code_mixed_with_data() {
+        let mut seed = 0x2545u32;
+        let mut rom = vec!(0xc3, 0x00, 0x02);
+        while rom.len() < 0x1000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            rom.push((seed >> 16) as u8);
+        }
+        round_trip(0, rom);
+    }
+
+    #[test]
+    fn reassembles_a_program_loaded_above_zero() {
+        //CALL 0108H, HLT, then a routine that loads a word stored past the code
+        let rom = vec!(0xcd, 0x08, 0x01, 0x76, 0x00, 0x00, 0x00, 0x00, 0x2a, 0x0d, 0x01, 0xc9, 0x00, 0x34, 0x12);
+        round_trip(0x100, rom.clone());
+        let mut cpu = CPU::empty();
+        cpu.load(0x100, &rom);
+        let mut source = vec!();
+        write_source(&cpu, 0x100, rom.len(), &mut source).unwrap();
+        let source = String::from_utf8(source).unwrap();
+        assert!(source.contains("ORG     0100H"), "{}", source);
+        assert!(source.contains("CALL    L0108"), "{}", source);
+        assert!(source.contains("LHLD    L010D"), "{}", source);
+    }
+}
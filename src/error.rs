@@ -0,0 +1,189 @@
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+#[cfg(feature = "std")]
+use std::io;
+
+use cpu::Address;
+use cpu::instruction::Instruction;
+
+//Everything that can go wrong getting a program into memory
+#[derive(Debug)]
+pub enum LoadError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    //The program doesn't fit in the 64K the 8080 can address
+    TooLarge { size: usize },
+    InvalidHex { line: usize, message: String },
+    MissingEndRecord,
+    NotASnapshot,
+    UnsupportedSnapshotVersion(u16),
+}
+
+//Why the bytes at an address aren't an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    //A byte the 8080 doesn't define as an opcode
+    UndefinedOpcode { addr: Address, byte: u8 },
+    //The input ended partway through an instruction, or before it; byte is the opcode if there was one
+    Truncated { addr: Address, byte: Option<u8> },
+}
+
+//A variant of Instruction that no opcode stands for, like LDAX H or RST 9
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncodeError(pub Instruction);
+
+//Why the CPU couldn't run the next instruction; the CPU is left as it was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionError {
+    UndefinedOpcode(DecodeError),
+    //Decoded, but no group of operations knows how to execute it
+    Unimplemented { addr: Address, instruction: Instruction },
+}
+
+//Any of the above, for callers that just want to pass errors along
+#[derive(Debug)]
+pub enum Error {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    Load(LoadError),
+    Decode(DecodeError),
+    Encode(EncodeError),
+    Execution(ExecutionError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            LoadError::Io(ref error) => write!(f, "{}", error),
+            LoadError::TooLarge { size } => write!(f, "{} bytes won't fit in 64K of memory", size),
+            LoadError::InvalidHex { line, ref message } => write!(f, "line {}: {}", line, message),
+            LoadError::MissingEndRecord => f.write_str("no end record"),
+            LoadError::NotASnapshot => f.write_str("not an 8080 snapshot"),
+            LoadError::UnsupportedSnapshotVersion(version) => write!(f, "snapshot version {} isn't supported, only version 1", version),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UndefinedOpcode { addr, byte } => write!(f, "undefined opcode {:02X}H at {:04X}H", byte, addr),
+            DecodeError::Truncated { addr, byte: Some(byte) } => write!(f, "opcode {:02X}H at {:04X}H is missing its operands", byte, addr),
+            DecodeError::Truncated { addr, byte: None } => write!(f, "no instruction at {:04X}H", addr),
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} has no 8080 opcode", self.0)
+    }
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExecutionError::UndefinedOpcode(ref error) => write!(f, "{}", error),
+            ExecutionError::Unimplemented { addr, instruction } => write!(f, "{} at {:04X}H isn't implemented", instruction, addr),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "std")]
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::Load(ref error) => write!(f, "{}", error),
+            Error::Decode(ref error) => write!(f, "{}", error),
+            Error::Encode(ref error) => write!(f, "{}", error),
+            Error::Execution(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            LoadError::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DecodeError {}
+
+#[cfg(feature = "std")]
+impl error::Error for EncodeError {}
+
+#[cfg(feature = "std")]
+impl error::Error for ExecutionError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ExecutionError::UndefinedOpcode(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            Error::Load(ref error) => Some(error),
+            Error::Decode(ref error) => Some(error),
+            Error::Encode(ref error) => Some(error),
+            Error::Execution(ref error) => Some(error),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for LoadError {
+    fn from(error: io::Error) -> LoadError {
+        LoadError::Io(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl From<LoadError> for Error {
+    fn from(error: LoadError) -> Error {
+        Error::Load(error)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Error {
+        Error::Decode(error)
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(error: EncodeError) -> Error {
+        Error::Encode(error)
+    }
+}
+
+impl From<ExecutionError> for Error {
+    fn from(error: ExecutionError) -> Error {
+        Error::Execution(error)
+    }
+}
+
+impl From<DecodeError> for ExecutionError {
+    fn from(error: DecodeError) -> ExecutionError {
+        ExecutionError::UndefinedOpcode(error)
+    }
+}
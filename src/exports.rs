@@ -0,0 +1,53 @@
+//What the C ABI and the WebAssembly exports have in common, so both number the registers and
+//run the CPU the same way
+use cpu::{CPU, Io};
+use cpu::condition::Condition;
+use cpu::register::{Register, RegisterPair};
+
+//Registers are numbered as the 8080 encodes them, 0 to 7 being B C D E H L M A, then these
+pub const REGISTER_FLAGS: u32 = 8;
+pub const REGISTER_SP: u32 = 9;
+pub const REGISTER_PC: u32 = 10;
+
+//Register M reads the byte HL points at. An unknown register reads as 0.
+pub fn get_register(cpu: &CPU, reg: u32) -> u16 {
+    match reg {
+        0..=7 => cpu.get_register(&Register::from(reg as u8)) as u16,
+        REGISTER_FLAGS => cpu.get_flags().to_byte() as u16,
+        REGISTER_SP => cpu.get_register_pair(RegisterPair::SP),
+        REGISTER_PC => cpu.get_pc(),
+        _ => 0,
+    }
+}
+
+//8 bit registers take the low byte of value. Setting an unknown register does nothing.
+pub fn set_register(cpu: &mut CPU, reg: u32, value: u16) {
+    match reg {
+        0..=7 => cpu.set_register(Register::from(reg as u8), value as u8),
+        REGISTER_FLAGS => cpu.set_flags(Condition::from_byte(value as u8)),
+        REGISTER_SP => cpu.set_register_pair(RegisterPair::SP, value),
+        REGISTER_PC => cpu.set_pc(value),
+        _ => {},
+    }
+}
+
+//The cycles the instruction took, or -1 without touching the CPU for an opcode it can't run
+pub fn step(cpu: &mut CPU, io: &mut dyn Io) -> i32 {
+    let start = cpu.get_cycles();
+    match cpu.try_step(io) {
+        Ok(_) => (cpu.get_cycles() - start) as i32,
+        Err(_) => -1,
+    }
+}
+
+//Runs for at least `cycles` cycles, stopping early if the CPU halts or reaches an opcode it
+//can't run, and returns how many it ran
+pub fn run(cpu: &mut CPU, io: &mut dyn Io, cycles: u64) -> u64 {
+    let start = cpu.get_cycles();
+    while cpu.get_cycles() - start < cycles && !cpu.is_halted() {
+        if step(cpu, io) < 0 {
+            break;
+        }
+    }
+    cpu.get_cycles() - start
+}
@@ -0,0 +1,212 @@
+//A C ABI for embedding the CPU in programs that aren't written in Rust. Link against the
+//library built with
+//  cargo build --lib --release --features ffi
+//and include include/eightyeightyemu.h, which is generated from this file; after changing the
+//declarations here, write it out again with
+//  cargo run --example gen-header
+//Anything taking an i8080 expects one from i8080_new that hasn't been through i8080_free, and
+//only i8080_free accepts null.
+#![allow(clippy::missing_safety_doc)]
+
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
+
+use cpu::{CPU, Io, Port};
+use exports;
+
+//Bumped whenever a declaration in the header changes in a way that breaks existing callers
+pub const ABI_VERSION: u32 = 1;
+
+//Registers are numbered as the 8080 encodes them, 0 to 7 being B C D E H L M A, then these
+pub const REGISTER_FLAGS: u32 = exports::REGISTER_FLAGS;
+pub const REGISTER_SP: u32 = exports::REGISTER_SP;
+pub const REGISTER_PC: u32 = exports::REGISTER_PC;
+
+//Called for IN with the port number; returns the byte read
+pub type InputCallback = extern "C" fn(user_data: *mut c_void, port: u8) -> u8;
+//Called for OUT with the port number and the byte written
+pub type OutputCallback = extern "C" fn(user_data: *mut c_void, port: u8, value: u8);
+
+//The callbacks, and whatever the caller wants them to be passed
+struct Ports {
+    input: Option<InputCallback>,
+    output: Option<OutputCallback>,
+    user_data: *mut c_void,
+}
+
+impl Io for Ports {
+    fn input(&mut self, port: Port) -> u8 {
+        match self.input {
+            Some(input) => input(self.user_data, port),
+            None => 0,
+        }
+    }
+
+    fn output(&mut self, port: Port, value: u8) {
+        if let Some(output) = self.output {
+            output(self.user_data, port, value);
+        }
+    }
+}
+
+//A CPU with 64K of memory and its ports; opaque to C
+pub struct I8080 {
+    cpu: CPU,
+    ports: Ports,
+}
+
+//The ABI_VERSION the library was built with
+#[no_mangle]
+pub extern "C" fn i8080_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+//A CPU at power on, with memory cleared and nothing on its ports. Never null.
+#[no_mangle]
+pub extern "C" fn i8080_new() -> *mut I8080 {
+    let ports = Ports { input: None, output: None, user_data: ptr::null_mut() };
+    Box::into_raw(Box::new(I8080 { cpu: CPU::empty(), ports }))
+}
+
+//cpu may be null, which is ignored
+#[no_mangle]
+pub unsafe extern "C" fn i8080_free(cpu: *mut I8080) {
+    if !cpu.is_null() {
+        drop(Box::from_raw(cpu));
+    }
+}
+
+//cpu must not be null. Either callback may be null: reads then see 0 and writes go nowhere.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_set_io(cpu: *mut I8080, input: Option<InputCallback>, output: Option<OutputCallback>, user_data: *mut c_void) {
+    (*cpu).ports = Ports { input, output, user_data };
+}
+
+//cpu must not be null. Copies len bytes into memory from addr, wrapping at the top of memory;
+//null bytes or a len of 0 loads nothing.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_load(cpu: *mut I8080, addr: u16, bytes: *const u8, len: usize) {
+    if bytes.is_null() || len == 0 {
+        return;
+    }
+    (*cpu).cpu.load(addr, slice::from_raw_parts(bytes, len));
+}
+
+//cpu must not be null
+#[no_mangle]
+pub unsafe extern "C" fn i8080_read_memory(cpu: *const I8080, addr: u16) -> u8 {
+    (*cpu).cpu.read_memory(addr)
+}
+
+//cpu must not be null
+#[no_mangle]
+pub unsafe extern "C" fn i8080_write_memory(cpu: *mut I8080, addr: u16, value: u8) {
+    (*cpu).cpu.write_memory(addr, value);
+}
+
+//cpu must not be null. Runs one instruction and returns the cycles it took, or -1 without
+//touching the CPU if the opcode is one the 8080 doesn't define.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_step(cpu: *mut I8080) -> i32 {
+    let cpu = &mut *cpu;
+    exports::step(&mut cpu.cpu, &mut cpu.ports)
+}
+
+//cpu must not be null. Runs for at least cycles cycles, stopping early if the CPU halts or
+//reaches an undefined opcode, and returns how many it ran.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_run(cpu: *mut I8080, cycles: u64) -> u64 {
+    let cpu = &mut *cpu;
+    exports::run(&mut cpu.cpu, &mut cpu.ports, cycles)
+}
+
+//cpu must not be null. Raises RST vector; returns whether the CPU had interrupts enabled and
+//so took it.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_interrupt(cpu: *mut I8080, vector: u8) -> bool {
+    (*cpu).cpu.interrupt(vector)
+}
+
+//cpu must not be null
+#[no_mangle]
+pub unsafe extern "C" fn i8080_halted(cpu: *const I8080) -> bool {
+    (*cpu).cpu.is_halted()
+}
+
+//cpu must not be null
+#[no_mangle]
+pub unsafe extern "C" fn i8080_cycles(cpu: *const I8080) -> u64 {
+    (*cpu).cpu.get_cycles()
+}
+
+//cpu must not be null. Register M reads the byte HL points at; an unknown register reads as 0.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_get_register(cpu: *const I8080, reg: u32) -> u16 {
+    exports::get_register(&(*cpu).cpu, reg)
+}
+
+//cpu must not be null. 8 bit registers take the low byte of value; setting an unknown
+//register does nothing.
+#[no_mangle]
+pub unsafe extern "C" fn i8080_set_register(cpu: *mut I8080, reg: u32, value: u16) {
+    exports::set_register(&mut (*cpu).cpu, reg, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::c_void;
+
+    use assembler::assemble;
+
+    use super::*;
+
+    //Echoes each OUT back on the next IN from the port after it, through the user data
+    extern "C" fn input(user_data: *mut c_void, port: u8) -> u8 {
+        let outputs = unsafe { &*(user_data as *const Vec<(u8, u8)>) };
+        outputs.iter().rev().find(|&&(out_port, _)| out_port + 1 == port).map_or(0xff, |&(_, value)| value)
+    }
+
+    extern "C" fn output(user_data: *mut c_void, port: u8, value: u8) {
+        unsafe { &mut *(user_data as *mut Vec<(u8, u8)>) }.push((port, value));
+    }
+
+    #[test]
+    fn runs_a_program_through_the_c_abi() {
+        let program = assemble("ORG 100H\nMVI A,41H\nOUT 10H\nIN 11H\nINR A\nOUT 20H\nDB 0DDH\nHLT").unwrap().to_binary();
+        let mut outputs: Vec<(u8, u8)> = vec!();
+        unsafe {
+            assert_eq!(i8080_abi_version(), ABI_VERSION);
+            let cpu = i8080_new();
+            i8080_set_io(cpu, Some(input), Some(output), &mut outputs as *mut Vec<(u8, u8)> as *mut c_void);
+            i8080_load(cpu, 0x100, program.as_ptr(), program.len());
+            i8080_load(cpu, 0x100, ptr::null(), 4);
+            i8080_load(cpu, 0x100, [0xff].as_ptr(), 0);
+            assert_eq!(i8080_read_memory(cpu, 0x100), 0x3e);
+            i8080_set_register(cpu, REGISTER_PC, 0x100);
+            assert_eq!(i8080_step(cpu), 7);
+            assert_eq!(i8080_get_register(cpu, 7), 0x41);
+            assert_eq!(i8080_run(cpu, 1_000), 10 + 10 + 5 + 10);
+            assert_eq!(i8080_get_register(cpu, REGISTER_PC), 0x109);
+            assert_eq!(i8080_step(cpu), -1);
+            assert_eq!(i8080_get_register(cpu, REGISTER_PC), 0x109);
+
+            i8080_set_register(cpu, REGISTER_PC, 0x10a);
+            assert_eq!(i8080_run(cpu, 1_000), 7);
+            assert!(i8080_halted(cpu));
+            assert_eq!(i8080_cycles(cpu), 7 + 10 + 10 + 5 + 10 + 7);
+
+            i8080_set_register(cpu, 4, 0x12);
+            i8080_set_register(cpu, 5, 0x34);
+            i8080_write_memory(cpu, 0x1234, 0x99);
+            assert_eq!(i8080_get_register(cpu, 6), 0x99);
+            i8080_set_register(cpu, REGISTER_SP, 0xf000);
+            i8080_set_register(cpu, REGISTER_FLAGS, 0xff);
+            assert_eq!(i8080_get_register(cpu, REGISTER_SP), 0xf000);
+            assert_eq!(i8080_get_register(cpu, REGISTER_FLAGS), 0xd7);
+            assert!(!i8080_interrupt(cpu, 7));
+            i8080_free(cpu);
+        }
+        assert_eq!(outputs, vec!((0x10, 0x41), (0x20, 0x42)));
+    }
+}
@@ -0,0 +1,448 @@
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use cpu::{CPU, Access, Address, Io};
+use cpu::condition::Condition;
+use cpu::register::{Register, RegisterPair};
+
+//GDB has no 8080 target, but its Z80 one (`set architecture z80`) starts with
+//AF, BC, DE, HL, SP and PC, so we describe the CPU that way. The Z80-only
+//registers after those (IX, IY, the shadow set and IR) always read as zero.
+const REGISTER_COUNT: usize = 13;
+const PACKET_SIZE: usize = 0x1000;
+//How many instructions run between checks for GDB asking to interrupt; a power of two
+const POLL_INTERVAL: u32 = 0x400;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WatchKind {
+    Write,
+    Read,
+    Access,
+}
+
+struct Watchpoint {
+    kind: WatchKind,
+    addr: Address,
+    length: u16,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &Access) -> Option<Address> {
+        let (addr, write) = match *access {
+            Access::Read { addr, .. } => (addr, false),
+            Access::Write { addr, .. } => (addr, true),
+            _ => return None,
+        };
+        let hit = match self.kind {
+            WatchKind::Write => write,
+            WatchKind::Read => !write,
+            WatchKind::Access => true,
+        };
+        if hit && addr.wrapping_sub(self.addr) < self.length {
+            Some(addr)
+        } else {
+            None
+        }
+    }
+}
+
+//A remote serial protocol stub for a single GDB connection
+pub struct GdbStub<'a> {
+    cpu: &'a mut CPU,
+    io: &'a mut dyn Io,
+    breakpoints: BTreeSet<Address>,
+    watchpoints: Vec<Watchpoint>,
+    stop_reply: String,
+    last_reply: String,
+}
+
+//Waits for GDB on `address` (e.g. 127.0.0.1:1234) and serves it until it detaches or kills the program
+pub fn serve(cpu: &mut CPU, io: &mut dyn Io, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Waiting for GDB on {}, connect with `target remote {}`.", listener.local_addr()?, listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("GDB connected from {}.", peer);
+    GdbStub::new(cpu, io).session(stream)
+}
+
+impl<'a> GdbStub<'a> {
+    pub fn new(cpu: &'a mut CPU, io: &'a mut dyn Io) -> GdbStub<'a> {
+        GdbStub {
+            cpu,
+            io,
+            breakpoints: BTreeSet::new(),
+            watchpoints: vec!(),
+            stop_reply: format!("S{:02x}", SIGTRAP),
+            last_reply: String::new(),
+        }
+    }
+
+    pub fn session(&mut self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        while let Some(packet) = self.receive(&mut stream)? {
+            let reply = match packet.as_bytes().first() {
+                Some(b'k') => return Ok(()),
+                Some(b'D') => {
+                    self.send(&mut stream, "OK")?;
+                    return Ok(());
+                },
+                Some(b'c') | Some(b's') => {
+                    if let Some(addr) = parse_hex(&packet[1..]) {
+                        self.cpu.set_pc(addr as Address);
+                    }
+                    let reply = self.resume(&mut stream, packet.starts_with('s'))?;
+                    self.stop_reply = reply.clone();
+                    reply
+                },
+                _ => self.handle(&packet),
+            };
+            self.send(&mut stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    //Everything except running the program and ending the session; unsupported packets get an empty reply
+    fn handle(&mut self, packet: &str) -> String {
+        if !packet.is_char_boundary(1) {
+            return String::new();
+        }
+        let (command, args) = packet.split_at(1);
+        match command {
+            "?" => self.stop_reply.clone(),
+            "g" => (0..REGISTER_COUNT).map(|reg| encode_word(self.read_register(reg))).collect(),
+            "G" => {
+                for (reg, word) in decode_bytes(args).unwrap_or_default().chunks(2).enumerate() {
+                    if word.len() == 2 {
+                        self.write_register(reg, word[0] as u16 | (word[1] as u16) << 8);
+                    }
+                }
+                "OK".to_string()
+            },
+            "p" => match parse_hex(args) {
+                Some(reg) if (reg as usize) < REGISTER_COUNT => encode_word(self.read_register(reg as usize)),
+                _ => "E01".to_string(),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let reg = parts.next().and_then(parse_hex);
+                let bytes = parts.next().and_then(decode_bytes);
+                match (reg, bytes) {
+                    (Some(reg), Some(ref bytes)) if (reg as usize) < REGISTER_COUNT && bytes.len() == 2 => {
+                        self.write_register(reg as usize, bytes[0] as u16 | (bytes[1] as u16) << 8);
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "m" => match parse_range(args) {
+                Some((addr, length)) => (0..length)
+                    .map(|offset| format!("{:02x}", self.cpu.read_memory(addr.wrapping_add(offset))))
+                    .collect(),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(parse_range);
+                let bytes = parts.next().and_then(decode_bytes);
+                match (range, bytes) {
+                    (Some((addr, length)), Some(ref bytes)) if bytes.len() == length as usize => {
+                        for (offset, byte) in bytes.iter().enumerate() {
+                            self.cpu.write_memory(addr.wrapping_add(offset as u16), *byte);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => "E01".to_string(),
+                }
+            },
+            "Z" | "z" => self.change_breakpoint(command == "Z", args),
+            "H" => "OK".to_string(),
+            "q" if args.starts_with("Supported") => format!("PacketSize={:x}", PACKET_SIZE),
+            "q" if args == "Attached" => "1".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    //Type 0 and 1 are breakpoints, 2, 3 and 4 are write, read and access watchpoints
+    fn change_breakpoint(&mut self, insert: bool, args: &str) -> String {
+        let mut parts = args.split(',');
+        let kind = parts.next().and_then(parse_hex);
+        let addr = parts.next().and_then(parse_hex);
+        let length = parts.next().and_then(parse_hex);
+        let (kind, addr, length) = match (kind, addr, length) {
+            (Some(kind), Some(addr), Some(length)) => (kind, addr as Address, length as u16),
+            _ => return "E01".to_string(),
+        };
+        let watch_kind = match kind {
+            0 | 1 => {
+                if insert {
+                    self.breakpoints.insert(addr);
+                } else {
+                    self.breakpoints.remove(&addr);
+                }
+                return "OK".to_string();
+            },
+            2 => WatchKind::Write,
+            3 => WatchKind::Read,
+            4 => WatchKind::Access,
+            _ => return String::new(),
+        };
+        if insert {
+            self.watchpoints.push(Watchpoint { kind: watch_kind, addr, length: length.max(1) });
+        } else {
+            self.watchpoints.retain(|watchpoint| watchpoint.kind != watch_kind || watchpoint.addr != addr);
+        }
+        "OK".to_string()
+    }
+
+    //Runs one instruction, or until something stops the program, and returns the stop reply
+    fn resume(&mut self, stream: &mut TcpStream, single_step: bool) -> io::Result<String> {
+        let mut count: u32 = 0;
+        loop {
+            if self.cpu.try_step(self.io).is_err() {
+                return Ok(format!("S{:02x}", SIGILL));
+            }
+            if let Some(reply) = self.watch_reply() {
+                return Ok(reply);
+            }
+            if single_step || self.cpu.is_halted() || self.breakpoints.contains(&self.cpu.get_pc()) {
+                return Ok(format!("S{:02x}", SIGTRAP));
+            }
+            count = count.wrapping_add(1);
+            if count & (POLL_INTERVAL - 1) == 0 && interrupt_requested(stream)? {
+                return Ok(format!("S{:02x}", SIGINT));
+            }
+        }
+    }
+
+    fn watch_reply(&self) -> Option<String> {
+        for access in self.cpu.get_accesses() {
+            for watchpoint in &self.watchpoints {
+                if let Some(addr) = watchpoint.matches(access) {
+                    let name = match watchpoint.kind {
+                        WatchKind::Write => "watch",
+                        WatchKind::Read => "rwatch",
+                        WatchKind::Access => "awatch",
+                    };
+                    return Some(format!("T{:02x}{}:{:x};", SIGTRAP, name, addr));
+                }
+            }
+        }
+        None
+    }
+
+    fn read_register(&self, reg: usize) -> u16 {
+        match reg {
+            0 => (self.cpu.get_register(&Register::A) as u16) << 8 | self.cpu.get_flags().to_byte() as u16,
+            1 => self.cpu.get_register_pair(RegisterPair::BC),
+            2 => self.cpu.get_register_pair(RegisterPair::DE),
+            3 => self.cpu.get_register_pair(RegisterPair::HL),
+            4 => self.cpu.get_register_pair(RegisterPair::SP),
+            5 => self.cpu.get_pc(),
+            _ => 0,
+        }
+    }
+
+    fn write_register(&mut self, reg: usize, value: u16) {
+        match reg {
+            0 => {
+                self.cpu.set_register(Register::A, (value >> 8) as u8);
+                self.cpu.set_flags(Condition::from_byte(value as u8));
+            },
+            1 => self.cpu.set_register_pair(RegisterPair::BC, value),
+            2 => self.cpu.set_register_pair(RegisterPair::DE, value),
+            3 => self.cpu.set_register_pair(RegisterPair::HL, value),
+            4 => self.cpu.set_register_pair(RegisterPair::SP, value),
+            5 => self.cpu.set_pc(value),
+            _ => {},
+        }
+    }
+
+    //Returns the next packet's contents once its checksum checks out, or None when GDB hangs up.
+    //Acknowledgements are skipped, except that a '-' asks for the last reply again.
+    fn receive(&mut self, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        loop {
+            match read_byte(stream)? {
+                None => return Ok(None),
+                Some(b'$') => {},
+                Some(b'-') => {
+                    let reply = self.last_reply.clone();
+                    self.send(stream, &reply)?;
+                    continue;
+                },
+                Some(_) => continue,
+            }
+            let mut data = vec!();
+            loop {
+                match read_byte(stream)? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0; 2];
+            stream.read_exact(&mut checksum)?;
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if expected != Some(checksum_of(&data)) {
+                stream.write_all(b"-")?;
+                continue;
+            }
+            stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+        }
+    }
+
+    fn send(&mut self, stream: &mut TcpStream, reply: &str) -> io::Result<()> {
+        write!(stream, "${}#{:02x}", reply, checksum_of(reply.as_bytes()))?;
+        self.last_reply = reply.to_string();
+        stream.flush()
+    }
+}
+
+fn read_byte(stream: &mut TcpStream) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+//GDB interrupts a running program by sending a bare 0x03
+fn interrupt_requested(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+    match result {
+        Ok(1) => Ok(byte[0] == 0x03),
+        Ok(_) => Ok(false),
+        Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+//`addr,length` as used by the m and M packets
+fn parse_range(text: &str) -> Option<(Address, u16)> {
+    let mut parts = text.split(',');
+    let addr = parts.next().and_then(parse_hex)?;
+    let length = parts.next().and_then(parse_hex)?;
+    if addr > 0xffff || length > 0x10000 {
+        return None;
+    }
+    Some((addr as Address, length as u16))
+}
+
+fn decode_bytes(text: &str) -> Option<Vec<u8>> {
+    if text.len() & 1 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+//Registers go over the wire in target byte order, which is little endian
+fn encode_word(word: u16) -> String {
+    format!("{:02x}{:02x}", word as u8, (word >> 8) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    use cpu::NoIo;
+    use testing::cpu_from_assembly;
+
+    use super::{GdbStub, checksum_of};
+
+    //Sends a packet the way GDB does and returns the reply's contents
+    fn exchange(stream: &mut TcpStream, packet: &str) -> String {
+        write!(stream, "${}#{:02x}", packet, checksum_of(packet.as_bytes())).unwrap();
+        let mut reply = vec!();
+        let mut byte = [0];
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            match byte[0] {
+                b'+' => continue,
+                b'#' => break,
+                b'$' => reply.clear(),
+                other => reply.push(other),
+            }
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn serves_registers_memory_and_breakpoints() {
+        let (mut cpu, _) = cpu_from_assembly("\
+        ORG     0
+START:  LXI     SP,100H
+        MVI     A,12H
+        LXI     H,VALUE
+        MOV     M,A
+DONE:   HLT
+VALUE:  DB      0
+");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut io = NoIo;
+            let (stream, _) = listener.accept().unwrap();
+            GdbStub::new(&mut cpu, &mut io).session(stream).unwrap();
+            cpu
+        });
+        let mut gdb = TcpStream::connect(addr).unwrap();
+        assert!(exchange(&mut gdb, "qSupported:swbreak+").starts_with("PacketSize="));
+        assert_eq!(exchange(&mut gdb, "?"), "S05");
+        assert_eq!(exchange(&mut gdb, "s"), "S05");
+        assert_eq!(exchange(&mut gdb, "p4"), "0001");
+        assert_eq!(exchange(&mut gdb, "m0,3"), "310001");
+        assert_eq!(exchange(&mut gdb, "Z2,a,1"), "OK");
+        assert_eq!(exchange(&mut gdb, "c"), "T05watch:a;");
+        assert_eq!(exchange(&mut gdb, "z2,a,1"), "OK");
+        assert_eq!(exchange(&mut gdb, "m0a,1"), "12");
+        assert_eq!(exchange(&mut gdb, "p5"), "0900");
+        assert_eq!(exchange(&mut gdb, "P1=3412"), "OK");
+        assert_eq!(exchange(&mut gdb, "M0a,1:00"), "OK");
+        assert_eq!(exchange(&mut gdb, "Z0,9,1"), "OK");
+        assert_eq!(exchange(&mut gdb, "c5"), "S05");
+        assert_eq!(&exchange(&mut gdb, "g")[..24], "0212341200000a0000010900");
+        assert_eq!(exchange(&mut gdb, "vMustReplyEmpty"), "");
+        assert_eq!(exchange(&mut gdb, "D"), "OK");
+        let cpu = server.join().unwrap();
+        assert_eq!(cpu.get_pc(), 0x0009);
+    }
+
+    #[test]
+    fn undefined_opcodes_stop_with_sigill() {
+        let (mut cpu, _) = cpu_from_assembly("MVI A,1\nDB 0DDH\nHLT");
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut io = NoIo;
+            let (stream, _) = listener.accept().unwrap();
+            GdbStub::new(&mut cpu, &mut io).session(stream).unwrap();
+            cpu
+        });
+        let mut gdb = TcpStream::connect(addr).unwrap();
+        assert_eq!(exchange(&mut gdb, "c"), "S04");
+        assert_eq!(exchange(&mut gdb, "s"), "S04");
+        assert_eq!(exchange(&mut gdb, "D"), "OK");
+        assert_eq!(server.join().unwrap().get_pc(), 0x0002);
+    }
+}
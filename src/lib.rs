@@ -0,0 +1,47 @@
+#![allow(clippy::upper_case_acronyms)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(any(feature = "std", test))]
+extern crate core;
+#[macro_use]
+extern crate alloc;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+//The 8080 emulator as a library: the CPU with its decoder and disassembler, the assembler
+//and linker, debugging front ends and the machines built around the CPU. The binaries in
+//main.rs and bin/ are thin layers over this.
+//Only the CPU core needs nothing more than core and alloc; everything that does I/O
+//is behind the std feature. Without it only the rlib builds, see Cargo.toml.
+pub mod cpu;
+pub mod error;
+#[cfg(feature = "std")]
+pub mod disassembly;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
+pub mod assembler;
+#[cfg(feature = "std")]
+pub mod linker;
+#[cfg(feature = "std")]
+pub mod loader;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod trace;
+#[cfg(feature = "std")]
+pub mod machine;
+#[cfg(feature = "std")]
+pub mod cli;
+#[cfg(any(feature = "wasm", feature = "ffi"))]
+mod exports;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(all(test, feature = "std"))]
+mod testing;
@@ -0,0 +1,193 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+use std::io::{self, Write};
+
+use assembler;
+use assembler::object::{ObjectModule, Segment};
+use cpu::Address;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkError {
+    pub message: String,
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl error::Error for LinkError {}
+
+pub struct Linked {
+    pub memory: BTreeMap<Address, u8>,
+    pub start: Option<Address>,
+    pub symbols: BTreeMap<String, Address>,
+}
+
+impl Linked {
+    pub fn to_binary(&self) -> Vec<u8> {
+        assembler::image(&self.memory)
+    }
+
+    pub fn write_hex<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        assembler::write_hex(&self.memory, self.start, out)
+    }
+}
+
+fn module_name(module: &ObjectModule, index: usize) -> String {
+    module.name.clone().unwrap_or_else(|| format!("module {}", index + 1))
+}
+
+//Every module's code segment is placed in turn from the origin, then every data segment
+//after the last of the code. Absolute segments stay where they were assembled.
+pub fn link(modules: &[ObjectModule], origin: Address) -> Result<Linked, Vec<LinkError>> {
+    let mut errors = vec!();
+    let mut bases = vec!();
+    let mut next = origin as usize;
+    for module in modules {
+        bases.push((next, 0));
+        next += module.code_size as usize;
+    }
+    for (module, base) in modules.iter().zip(bases.iter_mut()) {
+        base.1 = next;
+        next += module.data_size as usize;
+    }
+    if next > 0x10000 {
+        return Err(vec!(LinkError { message: format!("the linked program needs {} bytes past {:04X}H, more than memory holds",
+            next - origin as usize, origin) }));
+    }
+    let relocate = |index: usize, segment: Segment, value: u16| -> Address {
+        match segment {
+            Segment::Absolute => value,
+            Segment::Code => (bases[index].0 as u16).wrapping_add(value),
+            Segment::Data => (bases[index].1 as u16).wrapping_add(value),
+        }
+    };
+
+    let mut symbols: BTreeMap<String, Address> = BTreeMap::new();
+    for (index, module) in modules.iter().enumerate() {
+        for (name, segment, value) in &module.publics {
+            if symbols.insert(name.clone(), relocate(index, *segment, *value)).is_some() {
+                errors.push(LinkError { message: format!("{} is defined again in {}", name, module_name(module, index)) });
+            }
+        }
+    }
+
+    let mut memory = BTreeMap::new();
+    let mut start = None;
+    for (index, module) in modules.iter().enumerate() {
+        let mut fixups: HashMap<Address, u16> = HashMap::new();
+        for chunk in &module.chunks {
+            for &(offset, segment) in &chunk.relocations {
+                let addr = relocate(index, chunk.segment, chunk.offset.wrapping_add(offset as u16));
+                let word = chunk.bytes[offset] as u16 | (chunk.bytes.get(offset + 1).cloned().unwrap_or(0) as u16) << 8;
+                fixups.insert(addr, relocate(index, segment, word));
+            }
+        }
+        for external in &module.externals {
+            let addr = relocate(index, external.segment, external.offset);
+            match symbols.get(&external.name) {
+                Some(&value) => {
+                    fixups.insert(addr, value.wrapping_add(external.addend as u16));
+                },
+                None => errors.push(LinkError {
+                    message: format!("{} is used by {} but no module makes it PUBLIC", external.name, module_name(module, index)),
+                }),
+            }
+        }
+        for chunk in &module.chunks {
+            for (offset, &byte) in chunk.bytes.iter().enumerate() {
+                let addr = relocate(index, chunk.segment, chunk.offset.wrapping_add(offset as u16));
+                if memory.insert(addr, byte).is_some() {
+                    errors.push(LinkError { message: format!("{} overlaps other code at {:04X}H", module_name(module, index), addr) });
+                }
+            }
+        }
+        for (addr, word) in fixups {
+            memory.insert(addr, word as u8);
+            memory.insert(addr.wrapping_add(1), (word >> 8) as u8);
+        }
+        if let Some((segment, value)) = module.start {
+            if start.is_some() {
+                errors.push(LinkError { message: format!("{} has a second start address", module_name(module, index)) });
+            }
+            start = Some(relocate(index, segment, value));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(Linked { memory, start, symbols })
+}
+
+#[cfg(test)]
+mod tests {
+    use assembler::assemble;
+    use assembler::object::ObjectModule;
+
+    use super::link;
+
+    #[test]
+    fn links_modules_through_rel_files() {
+        let main = "\
+        NAME    MAIN
+        EXTRN   PRINT,COUNT
+        PUBLIC  MSG
+        CSEG
+START:  LXI     H,MSG
+        CALL    PRINT
+        LDA     COUNT+1
+        CALL    PRINT
+        JMP     START
+        DSEG
+MSG:    DB      'Hi',0
+        END     START
+";
+        let library = "\
+        NAME    LIB
+        EXTRN   MSG
+        PUBLIC  PRINT,COUNT
+        CSEG
+PRINT:  LXI     D,MSG
+        RET
+        DSEG
+COUNT:  DW      PRINT,0
+";
+        let mut modules = vec!();
+        for source in &[main, library] {
+            let assembly = assemble(source).unwrap_or_else(|errors| panic!("{}", errors[0]));
+            assert!(assembly.is_relocatable());
+            let object = assembly.to_object();
+            let mut rel = vec!();
+            object.write_rel(&mut rel).unwrap();
+            let read = ObjectModule::read_rel(&mut rel.as_slice()).unwrap();
+            assert_eq!(read, vec!(object));
+            modules.extend(read);
+        }
+        let linked = link(&modules, 0x100).unwrap_or_else(|errors| panic!("{}", errors[0]));
+        assert_eq!(linked.start, Some(0x100));
+        assert_eq!(linked.symbols["PRINT"], 0x10f);
+        assert_eq!(linked.symbols["MSG"], 0x113);
+        assert_eq!(linked.symbols["COUNT"], 0x116);
+        assert_eq!(linked.to_binary(), vec!(
+            0x21, 0x13, 0x01,
+            0xcd, 0x0f, 0x01,
+            0x3a, 0x17, 0x01,
+            0xcd, 0x0f, 0x01,
+            0xc3, 0x00, 0x01,
+            0x11, 0x13, 0x01,
+            0xc9,
+            b'H', b'i', 0x00,
+            0x0f, 0x01, 0x00, 0x00,
+        ));
+    }
+
+    #[test]
+    fn reports_missing_externals() {
+        let assembly = assemble("\tEXTRN\tNOWHERE\n\tCSEG\n\tJMP\tNOWHERE\n").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let errors = link(&[assembly.to_object()], 0x100).err().unwrap();
+        assert_eq!(errors[0].message, "NOWHERE is used by module 1 but no module makes it PUBLIC");
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, Read};
+use std::iter::FromIterator;
+
+use cpu::{CPU, Address};
+use error::LoadError;
+
+//Loads a raw image at 0000H
+pub fn load_cpu_with_instructions_from_file<R: Read>(mut reader: R) -> Result<CPU, LoadError>
+{
+    let mut opcode_buffer : Vec<u8> = vec!();
+    reader.read_to_end(&mut opcode_buffer)?;
+    CPU::new(VecDeque::from_iter(opcode_buffer))
+}
+
+//Reads Intel HEX: data records into a memory map, and the start address from a start
+//record or a non-zero end record, as the assembler and linker write them
+pub fn read_hex<R: BufRead>(input: R) -> Result<(BTreeMap<Address, u8>, Option<Address>), LoadError>
+{
+    let mut memory = BTreeMap::new();
+    let mut start = None;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let invalid = |message: &str| LoadError::InvalidHex { line: index + 1, message: message.to_string() };
+        let digits = match line.strip_prefix(':') {
+            Some(digits) if digits.len() % 2 == 0 => digits,
+            _ => return Err(invalid("not an Intel HEX record")),
+        };
+        let bytes = (0..digits.len()).step_by(2)
+            .map(|offset| u8::from_str_radix(&digits[offset..offset + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| invalid("not an Intel HEX record"))?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(invalid("record length doesn't match its data"));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(invalid("bad checksum"));
+        }
+        let addr = (bytes[1] as Address) << 8 | bytes[2] as Address;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => for (offset, byte) in data.iter().enumerate() {
+                memory.insert(addr.wrapping_add(offset as Address), *byte);
+            },
+            0x01 => {
+                if addr != 0 {
+                    start = Some(addr);
+                }
+                return Ok((memory, start));
+            },
+            0x03 if data.len() == 4 => start = Some((data[2] as Address) << 8 | data[3] as Address),
+            record_type => return Err(invalid(&format!("unsupported record type {:02X}H", record_type))),
+        }
+    }
+    Err(LoadError::MissingEndRecord)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assembler::write_hex;
+    use cpu::MEMORY_SIZE;
+    use error::LoadError;
+
+    use super::{load_cpu_with_instructions_from_file, read_hex};
+
+    #[test]
+    fn reads_back_what_the_assembler_writes() {
+        let mut memory = BTreeMap::new();
+        for addr in 0x100..0x125 {
+            memory.insert(addr, addr as u8 ^ 0x5a);
+        }
+        memory.insert(0x2000, 0x76);
+        let mut hex = vec!();
+        write_hex(&memory, Some(0x100), &mut hex).unwrap();
+        assert_eq!(read_hex(&hex[..]).unwrap(), (memory, Some(0x100)));
+
+        assert!(read_hex(&b":0100000076FF\n:00000001FF\n"[..]).is_err());
+        assert!(read_hex(&b":0100000076\n:00000001FF\n"[..]).is_err());
+        match read_hex(&b":0100000076 89\n"[..]) {
+            Err(LoadError::InvalidHex { line: 1, .. }) => {},
+            other => panic!("{:?}", other),
+        }
+        match read_hex(&b":010000007689\n"[..]) {
+            Err(LoadError::MissingEndRecord) => {},
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn loads_images_that_fit_in_memory() {
+        let cpu = load_cpu_with_instructions_from_file(&[0x3e, 0x01][..]).unwrap();
+        assert_eq!(cpu.read_memory(1), 0x01);
+        //a short image used to underflow while reporting the last instructions decoded
+        assert!(load_cpu_with_instructions_from_file(&[][..]).is_ok());
+        match load_cpu_with_instructions_from_file(&vec![0; MEMORY_SIZE + 1][..]) {
+            Err(LoadError::TooLarge { size }) => assert_eq!(size, MEMORY_SIZE + 1),
+            other => panic!("{:?}", other.err()),
+        }
+    }
+}
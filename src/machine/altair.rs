@@ -0,0 +1,239 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cpu::{CPU, Access, Address, Io, Port, MEMORY_SIZE};
+use error::{Error, ExecutionError};
+
+//The 8080 in an Altair runs at 2MHz; the terminal is serviced every 10ms of machine time
+const CLOCK_HZ: u64 = 2_000_000;
+const SLICE_CYCLES: u64 = CLOCK_HZ / 100;
+//Once input has run out, a second without output means the program is just waiting for more
+const IDLE_SLICES: u32 = 100;
+
+//The 88-SIO: status bits are active low, so 0 means ready
+const SIO_STATUS: Port = 0x00;
+const SIO_DATA: Port = 0x01;
+const SIO_INPUT_EMPTY: u8 = 0x01;
+//The 88-2SIO's first port is a 6850 ACIA, whose status bits are active high
+const ACIA_STATUS: Port = 0x10;
+const ACIA_DATA: Port = 0x11;
+const ACIA_INPUT_FULL: u8 = 0x01;
+const ACIA_OUTPUT_EMPTY: u8 = 0x02;
+const SENSE_SWITCHES: Port = 0xff;
+
+//Memory with no card behind it floats high
+const NO_MEMORY: u8 = 0xff;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AltairConfig {
+    //Bytes of RAM from 0000H up
+    pub ram_size: usize,
+    //What the front panel's upper eight address switches are set to
+    pub sense_switches: u8,
+}
+
+impl Default for AltairConfig {
+    fn default() -> AltairConfig {
+        AltairConfig { ram_size: MEMORY_SIZE, sense_switches: 0 }
+    }
+}
+
+//The front panel and the serial boards. Both an 88-SIO and an 88-2SIO are fitted,
+//and they share one terminal.
+struct Bus {
+    sense_switches: u8,
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+}
+
+impl Io for Bus {
+    fn input(&mut self, port: Port) -> u8 {
+        match port {
+            SIO_STATUS => if self.input.is_empty() { SIO_INPUT_EMPTY } else { 0 },
+            ACIA_STATUS => ACIA_OUTPUT_EMPTY | if self.input.is_empty() { 0 } else { ACIA_INPUT_FULL },
+            SIO_DATA | ACIA_DATA => self.input.pop_front().unwrap_or(0),
+            SENSE_SWITCHES => self.sense_switches,
+            _ => 0xff,
+        }
+    }
+
+    fn output(&mut self, port: Port, value: u8) {
+        //Software of the time often sets the parity bit, so only seven bits reach the terminal
+        if port == SIO_DATA || port == ACIA_DATA {
+            self.output.push(value & 0x7f);
+        }
+    }
+}
+
+pub struct Altair {
+    cpu: CPU,
+    bus: Bus,
+    ram_size: usize,
+}
+
+impl Altair {
+    pub fn new(config: AltairConfig) -> Altair {
+        let mut cpu = CPU::empty();
+        let ram_size = config.ram_size.min(MEMORY_SIZE);
+        for addr in ram_size..MEMORY_SIZE {
+            cpu.write_memory(addr as Address, NO_MEMORY);
+        }
+        Altair {
+            cpu,
+            bus: Bus { sense_switches: config.sense_switches, input: VecDeque::new(), output: vec!() },
+            ram_size,
+        }
+    }
+
+    //Puts a program in memory as if it had been toggled in or loaded from tape, and starts it there
+    pub fn load(&mut self, addr: Address, program: &[u8]) {
+        let end = (addr as usize + program.len()).min(self.ram_size);
+        if (addr as usize) < end {
+            self.cpu.load(addr, &program[..end - addr as usize]);
+        }
+        self.cpu.set_pc(addr);
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    //Typing at the terminal; newlines become the carriage returns the Altair expects
+    pub fn type_input(&mut self, text: &[u8]) {
+        self.bus.input.extend(text.iter().map(|byte| if *byte == b'\n' { b'\r' } else { *byte }));
+    }
+
+    //Everything sent to the terminal since the last call
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.bus.output.split_off(0)
+    }
+
+    //Runs for at least `cycles` cycles, or until the CPU halts or reaches an opcode it can't run
+    pub fn run(&mut self, cycles: u64) -> Result<(), ExecutionError> {
+        let end = self.cpu.get_cycles() + cycles;
+        while self.cpu.get_cycles() < end && !self.cpu.is_halted() {
+            self.cpu.try_step(&mut self.bus)?;
+            //The CPU sees 64K of RAM, so undo writes past the end of the real thing
+            if self.ram_size < MEMORY_SIZE {
+                for index in 0..self.cpu.get_accesses().len() {
+                    if let Access::Write { addr, old, .. } = self.cpu.get_accesses()[index] {
+                        if addr as usize >= self.ram_size {
+                            self.cpu.write_memory(addr, old);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    //Bridges the serial boards to stdin and stdout at the Altair's real speed. Returns when
+    //the CPU halts, or when stdin has ended and the program has gone quiet waiting for more,
+    //and fails if the CPU reaches an opcode it can't run.
+    pub fn run_on_terminal(&mut self) -> Result<(), Error> {
+        let keys = spawn_stdin_reader();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let mut input_ended = false;
+        let mut idle = 0;
+        while !self.cpu.is_halted() {
+            let started = Instant::now();
+            loop {
+                match keys.try_recv() {
+                    Ok(byte) => self.type_input(&[byte]),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        input_ended = true;
+                        break;
+                    },
+                }
+            }
+            let result = self.run(SLICE_CYCLES);
+            let output = self.take_output();
+            idle = if output.is_empty() && self.bus.input.is_empty() { idle + 1 } else { 0 };
+            stdout.write_all(&output)?;
+            stdout.flush()?;
+            result?;
+            if input_ended && idle >= IDLE_SLICES {
+                break;
+            }
+            let slice = Duration::from_millis(1000 * SLICE_CYCLES / CLOCK_HZ);
+            if let Some(remaining) = slice.checked_sub(started.elapsed()) {
+                thread::sleep(remaining);
+            }
+        }
+        writeln!(stdout)?;
+        Ok(())
+    }
+}
+
+//Reading stdin blocks, so it happens on its own thread and the bytes arrive over a channel
+fn spawn_stdin_reader() -> Receiver<u8> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for byte in io::stdin().lock().bytes() {
+            match byte {
+                Ok(byte) if sender.send(byte).is_ok() => {},
+                _ => break,
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use assembler::assemble;
+
+    use super::{Altair, AltairConfig};
+
+    //Greets on both serial boards, echoes a line in upper case on the 2SIO, stores the
+    //sense switches in RAM and also tries to store them past the end of RAM
+    #[test]
+    fn runs_programs_against_the_serial_boards() {
+        let assembly = assemble("\
+        ORG     0
+        LXI     SP,1000H
+        MVI     A,'S'
+        OUT     1
+        MVI     A,0C1H
+        OUT     11H
+ECHO:   IN      10H
+        RRC
+        JNC     ECHO
+        IN      11H
+        CPI     0DH
+        JZ      DONE
+        ANI     0DFH
+        MOV     B,A
+WAIT:   IN      10H
+        ANI     2
+        JZ      WAIT
+        MOV     A,B
+        OUT     11H
+        JMP     ECHO
+DONE:   IN      0
+        STA     100H
+        IN      0FFH
+        STA     101H
+        STA     1000H
+        LDA     1000H
+        STA     102H
+        HLT
+").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let mut altair = Altair::new(AltairConfig { ram_size: 0x1000, sense_switches: 0x5a });
+        altair.load(0, &assembly.to_binary());
+        altair.type_input(b"hey\n");
+        altair.run(100000).unwrap();
+        assert!(altair.cpu().is_halted());
+        assert_eq!(altair.take_output(), b"SAHEY".to_vec());
+        assert!(altair.take_output().is_empty());
+        let cpu = altair.cpu();
+        assert_eq!(cpu.read_memory(0x100), 0x01);
+        assert_eq!(cpu.read_memory(0x101), 0x5a);
+        assert_eq!(cpu.read_memory(0x102), 0xff);
+    }
+}
@@ -0,0 +1,678 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use cpu::{CPU, Address, NoIo};
+use error::ExecutionError;
+use cpu::register::{Register, RegisterPair};
+
+//Where CP/M loads and starts transient programs
+pub const TPA_START: Address = 0x0100;
+const WARM_BOOT: Address = 0x0000;
+const BDOS_CALL: Address = 0x0005;
+//The BDOS entry point is also the top of the TPA, which programs find at 0006H
+const BDOS_ENTRY: Address = 0xfe00;
+const BIOS: Address = 0xff00;
+const BIOS_ENTRIES: u16 = 17;
+
+const DEFAULT_FCB: Address = 0x005c;
+const SECOND_FCB: Address = 0x006c;
+const DEFAULT_DMA: Address = 0x0080;
+const RECORD_SIZE: usize = 128;
+const FCB_NAME_LENGTH: usize = 11;
+//Short records are padded with ^Z, CP/M's end of file for text
+const EOF_MARK: u8 = 0x1a;
+const CPM_VERSION: u16 = 0x0022;
+
+//FCB fields, as offsets from its start
+const FCB_EX: u16 = 12;
+const FCB_S2: u16 = 14;
+const FCB_RC: u16 = 15;
+const FCB_CR: u16 = 32;
+const FCB_R0: u16 = 33;
+
+//Why a program stopped running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    //Jumped to 0000H, returned from the program or called BDOS function 0
+    WarmBoot,
+    Halted,
+    //Ran the number of instructions it was allowed
+    Limit,
+    //Reached an opcode the CPU can't run
+    Fault(ExecutionError),
+}
+
+//Just enough of CP/M 2.2 to run .COM files: the BDOS console and file functions are
+//trapped and carried out on the host, with drive A: being a host directory.
+pub struct Cpm {
+    cpu: CPU,
+    dir: PathBuf,
+    dma: Address,
+    search_results: VecDeque<[u8; FCB_NAME_LENGTH]>,
+}
+
+impl Cpm {
+    //Loads `program` at 0100H with `args` as its command tail, as if typed after its name
+    pub fn new(program: &[u8], args: &str, dir: &Path) -> Cpm {
+        let mut cpu = CPU::empty();
+        cpu.load(TPA_START, program);
+        cpu.load(WARM_BOOT, &jump(BIOS + 3));
+        cpu.load(BDOS_CALL, &jump(BDOS_ENTRY));
+        for entry in 0..BIOS_ENTRIES {
+            cpu.write_memory(BIOS + entry * 3, 0xc9);
+        }
+        let args = args.trim().to_ascii_uppercase();
+        let tail = if args.is_empty() { String::new() } else { format!(" {}", args) };
+        let tail = &tail.as_bytes()[..tail.len().min(RECORD_SIZE - 1)];
+        cpu.write_memory(DEFAULT_DMA, tail.len() as u8);
+        cpu.load(DEFAULT_DMA + 1, tail);
+        let mut words = args.split_whitespace();
+        cpu.load(DEFAULT_FCB, &parse_file_name(words.next().unwrap_or("")));
+        cpu.load(SECOND_FCB, &parse_file_name(words.next().unwrap_or("")));
+        //The CCP calls the program, so returning from it warm boots
+        cpu.set_register_pair(RegisterPair::SP, BDOS_ENTRY);
+        cpu.push(WARM_BOOT);
+        cpu.set_pc(TPA_START);
+        Cpm { cpu, dir: dir.to_path_buf(), dma: DEFAULT_DMA, search_results: VecDeque::new() }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    //Runs the program until it exits, halts, faults or has run `limit` instructions
+    pub fn run(&mut self, console_in: &mut dyn BufRead, console_out: &mut dyn Write, limit: Option<u64>) -> io::Result<Exit> {
+        let mut count = 0;
+        loop {
+            let pc = self.cpu.get_pc();
+            if pc == WARM_BOOT {
+                console_out.flush()?;
+                return Ok(Exit::WarmBoot);
+            }
+            if pc == BDOS_CALL || ((BIOS..BIOS + BIOS_ENTRIES * 3).contains(&pc) && (pc - BIOS) % 3 == 0) {
+                let exit = if pc == BDOS_CALL {
+                    self.bdos(console_in, console_out)?
+                } else {
+                    self.bios((pc - BIOS) / 3, console_in, console_out)?
+                };
+                if exit {
+                    console_out.flush()?;
+                    return Ok(Exit::WarmBoot);
+                }
+                let ret = self.cpu.pop();
+                self.cpu.set_pc(ret);
+                continue;
+            }
+            if self.cpu.is_halted() {
+                console_out.flush()?;
+                return Ok(Exit::Halted);
+            }
+            if Some(count) == limit {
+                console_out.flush()?;
+                return Ok(Exit::Limit);
+            }
+            //CP/M programs talk to the BDOS, never to ports
+            if let Err(error) = self.cpu.try_step(&mut NoIo) {
+                console_out.flush()?;
+                return Ok(Exit::Fault(error));
+            }
+            count += 1;
+        }
+    }
+
+    //Carries out the BDOS function in C, returning true if the program asked to exit
+    fn bdos(&mut self, console_in: &mut dyn BufRead, console_out: &mut dyn Write) -> io::Result<bool> {
+        let function = self.cpu.get_register(&Register::C);
+        let e = self.cpu.get_register(&Register::E);
+        let de = self.cpu.get_register_pair(RegisterPair::DE);
+        let result = match function {
+            0 => return Ok(true),
+            1 => {
+                let byte = read_console(console_in)?.unwrap_or(EOF_MARK);
+                console_out.write_all(&[byte])?;
+                byte as u16
+            },
+            2 => {
+                console_out.write_all(&[e])?;
+                0
+            },
+            //Printer and punch output go nowhere; the reader is always at end of file
+            3 => EOF_MARK as u16,
+            4 | 5 => 0,
+            6 => match e {
+                0xff => read_console(console_in)?.unwrap_or(0) as u16,
+                0xfe => console_status(console_in)?,
+                _ => {
+                    console_out.write_all(&[e])?;
+                    0
+                },
+            },
+            9 => {
+                let mut addr = de;
+                loop {
+                    let byte = self.cpu.read_memory(addr);
+                    if byte == b'$' {
+                        break;
+                    }
+                    console_out.write_all(&[byte])?;
+                    addr = addr.wrapping_add(1);
+                }
+                0
+            },
+            10 => {
+                self.read_console_buffer(de, console_in, console_out)?;
+                0
+            },
+            11 => console_status(console_in)?,
+            12 => CPM_VERSION,
+            13 => {
+                self.dma = DEFAULT_DMA;
+                0
+            },
+            //There is only drive A: and user 0
+            14 | 25 | 32 => 0,
+            15 => self.open_file(de),
+            16 => if self.find(de).is_some() { 0 } else { 0xff },
+            17 => {
+                self.search_results = self.matching(de).into_iter().collect();
+                self.next_search_result()
+            },
+            18 => self.next_search_result(),
+            19 => {
+                let found = self.matching(de);
+                for name in &found {
+                    if let Some(path) = self.host_path(name) {
+                        let _ = fs::remove_file(path);
+                    }
+                }
+                if found.is_empty() { 0xff } else { 0 }
+            },
+            20 => self.read_sequential(de),
+            21 => self.write_sequential(de),
+            22 => self.make_file(de),
+            23 => self.rename_file(de),
+            26 => {
+                self.dma = de;
+                0
+            },
+            33 => {
+                let record = self.random_record(de);
+                self.set_sequential_record(de, record);
+                self.read_record(de, record)
+            },
+            34 => {
+                let record = self.random_record(de);
+                self.set_sequential_record(de, record);
+                self.write_record(de, record)
+            },
+            35 => {
+                let size = self.find(de).and_then(|path| fs::metadata(path).ok()).map(|meta| meta.len()).unwrap_or(0);
+                let records = (size as usize).div_ceil(RECORD_SIZE);
+                self.set_random_record(de, records);
+                0
+            },
+            36 => {
+                let record = self.sequential_record(de);
+                self.set_random_record(de, record);
+                0
+            },
+            _ => 0,
+        };
+        self.set_result(result);
+        Ok(false)
+    }
+
+    //The BIOS jump table: cold and warm boot exit, and the console entries work
+    fn bios(&mut self, entry: u16, console_in: &mut dyn BufRead, console_out: &mut dyn Write) -> io::Result<bool> {
+        match entry {
+            0 | 1 => return Ok(true),
+            2 => {
+                let status = console_status(console_in)?;
+                self.cpu.set_register(Register::A, status as u8);
+            },
+            3 => {
+                let byte = read_console(console_in)?.unwrap_or(EOF_MARK);
+                self.cpu.set_register(Register::A, byte);
+            },
+            4 => {
+                let byte = self.cpu.get_register(&Register::C);
+                console_out.write_all(&[byte])?;
+            },
+            _ => self.cpu.set_register(Register::A, 0),
+        }
+        Ok(false)
+    }
+
+    //BDOS results come back in HL, with A a copy of L and B a copy of H
+    fn set_result(&mut self, result: u16) {
+        self.cpu.set_register_pair(RegisterPair::HL, result);
+        self.cpu.set_register(Register::A, result as u8);
+        self.cpu.set_register(Register::B, (result >> 8) as u8);
+    }
+
+    //Function 10: the first byte of the buffer says how much it holds, the second gets the length read
+    fn read_console_buffer(&mut self, buffer: Address, console_in: &mut dyn BufRead, console_out: &mut dyn Write) -> io::Result<()> {
+        let capacity = self.cpu.read_memory(buffer) as usize;
+        let mut line = vec!();
+        console_in.read_until(b'\n', &mut line)?;
+        while line.last() == Some(&b'\n') || line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        line.truncate(capacity);
+        console_out.write_all(&line)?;
+        console_out.write_all(b"\r\n")?;
+        self.cpu.write_memory(buffer.wrapping_add(1), line.len() as u8);
+        self.cpu.load(buffer.wrapping_add(2), &line);
+        Ok(())
+    }
+
+    fn fcb_name(&self, fcb: Address) -> [u8; FCB_NAME_LENGTH] {
+        let mut name = [0; FCB_NAME_LENGTH];
+        for (i, byte) in name.iter_mut().enumerate() {
+            //The top bits of the name are file attributes
+            *byte = (self.cpu.read_memory(fcb.wrapping_add(1 + i as u16)) & 0x7f).to_ascii_uppercase();
+        }
+        name
+    }
+
+    //Host files whose names fit CP/M's 8.3 and match the FCB, where ? matches anything
+    fn matching(&self, fcb: Address) -> Vec<[u8; FCB_NAME_LENGTH]> {
+        let pattern = self.fcb_name(fcb);
+        let mut found: Vec<[u8; FCB_NAME_LENGTH]> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_file())
+                .filter_map(|entry| entry.file_name().to_str().and_then(to_fcb_name))
+                .filter(|name| name.iter().zip(pattern.iter()).all(|(c, p)| *p == b'?' || c == p))
+                .collect(),
+            Err(_) => vec!(),
+        };
+        found.sort();
+        found
+    }
+
+    fn find(&self, fcb: Address) -> Option<PathBuf> {
+        let name = self.fcb_name(fcb);
+        self.host_path(&name)
+    }
+
+    //The host file for an FCB name, matched without regard to case
+    fn host_path(&self, name: &[u8; FCB_NAME_LENGTH]) -> Option<PathBuf> {
+        fs::read_dir(&self.dir).ok()?
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().to_str().and_then(to_fcb_name).as_ref() == Some(name))
+            .map(|entry| entry.path())
+    }
+
+    fn next_search_result(&mut self) -> u16 {
+        match self.search_results.pop_front() {
+            Some(name) => {
+                //A directory entry: user number, name, then an empty extent and allocation
+                let mut entry = [0; 32];
+                entry[1..1 + FCB_NAME_LENGTH].copy_from_slice(&name);
+                self.cpu.load(self.dma, &entry);
+                0
+            },
+            None => 0xff,
+        }
+    }
+
+    fn open_file(&mut self, fcb: Address) -> u16 {
+        match self.find(fcb) {
+            Some(path) => {
+                let size = fs::metadata(path).map(|meta| meta.len() as usize).unwrap_or(0);
+                let extent = self.cpu.read_memory(fcb.wrapping_add(FCB_EX)) as usize;
+                let records = size.div_ceil(RECORD_SIZE).saturating_sub(extent * RECORD_SIZE).min(RECORD_SIZE);
+                self.cpu.write_memory(fcb.wrapping_add(FCB_S2), 0);
+                self.cpu.write_memory(fcb.wrapping_add(FCB_RC), records as u8);
+                0
+            },
+            None => 0xff,
+        }
+    }
+
+    fn make_file(&mut self, fcb: Address) -> u16 {
+        let name = self.fcb_name(fcb);
+        if name.contains(&b'?') {
+            return 0xff;
+        }
+        let path = self.host_path(&name).unwrap_or_else(|| self.dir.join(host_name(&name)));
+        match File::create(path) {
+            Ok(_) => {
+                self.cpu.write_memory(fcb.wrapping_add(FCB_EX), 0);
+                self.cpu.write_memory(fcb.wrapping_add(FCB_S2), 0);
+                self.cpu.write_memory(fcb.wrapping_add(FCB_RC), 0);
+                0
+            },
+            Err(_) => 0xff,
+        }
+    }
+
+    //The new name is in the second half of the FCB
+    fn rename_file(&mut self, fcb: Address) -> u16 {
+        let new_name = self.fcb_name(fcb.wrapping_add(16));
+        match self.find(fcb) {
+            Some(ref path) if fs::rename(path, self.dir.join(host_name(&new_name))).is_ok() => 0,
+            _ => 0xff,
+        }
+    }
+
+    fn sequential_record(&self, fcb: Address) -> usize {
+        let extent = (self.cpu.read_memory(fcb.wrapping_add(FCB_EX)) & 0x1f) as usize | (self.cpu.read_memory(fcb.wrapping_add(FCB_S2)) as usize) << 5;
+        extent * RECORD_SIZE + self.cpu.read_memory(fcb.wrapping_add(FCB_CR)) as usize
+    }
+
+    fn set_sequential_record(&mut self, fcb: Address, record: usize) {
+        self.cpu.write_memory(fcb.wrapping_add(FCB_CR), (record % RECORD_SIZE) as u8);
+        self.cpu.write_memory(fcb.wrapping_add(FCB_EX), ((record / RECORD_SIZE) & 0x1f) as u8);
+        self.cpu.write_memory(fcb.wrapping_add(FCB_S2), ((record / RECORD_SIZE) >> 5) as u8);
+    }
+
+    fn random_record(&self, fcb: Address) -> usize {
+        self.cpu.read_memory(fcb.wrapping_add(FCB_R0)) as usize | (self.cpu.read_memory(fcb.wrapping_add(FCB_R0 + 1)) as usize) << 8
+    }
+
+    fn set_random_record(&mut self, fcb: Address, record: usize) {
+        self.cpu.load(fcb.wrapping_add(FCB_R0), &[record as u8, (record >> 8) as u8, (record >> 16) as u8]);
+    }
+
+    fn read_sequential(&mut self, fcb: Address) -> u16 {
+        let record = self.sequential_record(fcb);
+        let result = self.read_record(fcb, record);
+        if result == 0 {
+            self.set_sequential_record(fcb, record + 1);
+        }
+        result
+    }
+
+    fn write_sequential(&mut self, fcb: Address) -> u16 {
+        let record = self.sequential_record(fcb);
+        let result = self.write_record(fcb, record);
+        if result == 0 {
+            self.set_sequential_record(fcb, record + 1);
+        }
+        result
+    }
+
+    //Returns 0 after reading a record into the DMA buffer, or 1 at end of file
+    fn read_record(&mut self, fcb: Address, record: usize) -> u16 {
+        let path = match self.find(fcb) {
+            Some(path) => path,
+            None => return 1,
+        };
+        let mut buffer = [EOF_MARK; RECORD_SIZE];
+        let read = File::open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start((record * RECORD_SIZE) as u64))?;
+            let mut total = 0;
+            loop {
+                match file.read(&mut buffer[total..])? {
+                    0 => return Ok(total),
+                    count => total += count,
+                }
+            }
+        });
+        match read {
+            Ok(0) | Err(_) => 1,
+            Ok(_) => {
+                self.cpu.load(self.dma, &buffer);
+                0
+            },
+        }
+    }
+
+    //Returns 0 after writing the DMA buffer to a record, or 2 if the host refused it
+    fn write_record(&mut self, fcb: Address, record: usize) -> u16 {
+        let path = match self.find(fcb) {
+            Some(path) => path,
+            None => return 2,
+        };
+        let buffer: Vec<u8> = (0..RECORD_SIZE).map(|i| self.cpu.read_memory(self.dma.wrapping_add(i as u16))).collect();
+        let written = OpenOptions::new().write(true).open(path).and_then(|mut file| {
+            file.seek(SeekFrom::Start((record * RECORD_SIZE) as u64))?;
+            file.write_all(&buffer)
+        });
+        match written {
+            Ok(()) => 0,
+            Err(_) => 2,
+        }
+    }
+}
+
+fn jump(addr: Address) -> [u8; 3] {
+    [0xc3, addr as u8, (addr >> 8) as u8]
+}
+
+fn read_console(console_in: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    let byte = console_in.fill_buf()?.first().cloned();
+    if byte.is_some() {
+        console_in.consume(1);
+    }
+    //CP/M programs expect CR at the end of a line
+    Ok(byte.map(|byte| if byte == b'\n' { b'\r' } else { byte }))
+}
+
+//0FFH when a character is waiting; at the end of input nothing ever will be
+fn console_status(console_in: &mut dyn BufRead) -> io::Result<u16> {
+    Ok(if console_in.fill_buf()?.is_empty() { 0 } else { 0xff })
+}
+
+//Fills in the drive, name and type of an FCB from e.g. A:NAME.TYP, expanding * into ?s
+fn parse_file_name(word: &str) -> [u8; 16] {
+    let mut fcb = [0; 16];
+    let word = match word.as_bytes() {
+        [drive, b':', ..] => {
+            fcb[0] = drive.to_ascii_uppercase().wrapping_sub(b'A').wrapping_add(1);
+            &word[2..]
+        },
+        _ => word,
+    };
+    let mut parts = word.splitn(2, '.');
+    fill_name_field(&mut fcb[1..9], parts.next().unwrap_or(""));
+    fill_name_field(&mut fcb[9..12], parts.next().unwrap_or(""));
+    fcb
+}
+
+fn fill_name_field(field: &mut [u8], text: &str) {
+    let mut chars = text.bytes();
+    let mut wildcard = false;
+    for byte in field.iter_mut() {
+        *byte = if wildcard {
+            b'?'
+        } else {
+            match chars.next() {
+                Some(b'*') => {
+                    wildcard = true;
+                    b'?'
+                },
+                Some(c) => c.to_ascii_uppercase(),
+                None => b' ',
+            }
+        };
+    }
+}
+
+//A host file name as CP/M would write it in an FCB, if it fits in 8.3
+fn to_fcb_name(name: &str) -> Option<[u8; FCB_NAME_LENGTH]> {
+    let mut parts = name.splitn(2, '.');
+    let base = parts.next()?;
+    let extension = parts.next().unwrap_or("");
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 || extension.contains('.')
+        || !name.bytes().all(|c| c.is_ascii_graphic()) {
+        return None;
+    }
+    let mut fcb_name = [b' '; FCB_NAME_LENGTH];
+    for (i, c) in base.bytes().enumerate() {
+        fcb_name[i] = c.to_ascii_uppercase();
+    }
+    for (i, c) in extension.bytes().enumerate() {
+        fcb_name[8 + i] = c.to_ascii_uppercase();
+    }
+    Some(fcb_name)
+}
+
+fn host_name(name: &[u8; FCB_NAME_LENGTH]) -> String {
+    let base = String::from_utf8_lossy(&name[..8]).trim_end().to_string();
+    let extension = String::from_utf8_lossy(&name[8..]).trim_end().to_string();
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+
+    use assembler::assemble;
+    use error::{DecodeError, ExecutionError};
+    use testing::TempDir;
+
+    use super::{Cpm, Exit};
+
+    //Prints its command tail, copies the file named in it to COPY.TXT a record at a time,
+    //lists the .TXT files and then returns to CP/M
+    #[test]
+    fn runs_com_files_against_a_host_directory() {
+        let temp = TempDir::new();
+        let dir = &temp.0;
+        fs::write(dir.join("input.txt"), vec![b'x'; 200]).unwrap();
+        let assembly = assemble("\
+BDOS    EQU     5
+FCB     EQU     5CH
+DMA     EQU     80H
+        ORG     100H
+        LXI     D,HELLO
+        MVI     C,9
+        CALL    BDOS
+        LDA     DMA
+        MOV     B,A
+        LXI     H,DMA+1
+TAIL:   MOV     E,M
+        MVI     C,2
+        PUSH    B
+        PUSH    H
+        CALL    BDOS
+        POP     H
+        POP     B
+        INX     H
+        DCR     B
+        JNZ     TAIL
+        LXI     D,FCB
+        MVI     C,15
+        CALL    BDOS
+        LXI     D,OUTFCB
+        MVI     C,22
+        CALL    BDOS
+COPY:   LXI     D,FCB
+        MVI     C,20
+        CALL    BDOS
+        ORA     A
+        JNZ     DONE
+        LXI     D,OUTFCB
+        MVI     C,21
+        CALL    BDOS
+        JMP     COPY
+DONE:   LXI     D,OUTFCB
+        MVI     C,16
+        CALL    BDOS
+        LXI     D,DIRBUF
+        MVI     C,26
+        CALL    BDOS
+        LXI     D,WILD
+        MVI     C,17
+LIST:   CALL    BDOS
+        INR     A
+        RZ
+        MVI     E,' '
+        MVI     C,2
+        CALL    BDOS
+        LXI     H,DIRBUF+1
+        MVI     B,11
+NAME:   MOV     E,M
+        MVI     C,2
+        PUSH    B
+        PUSH    H
+        CALL    BDOS
+        POP     H
+        POP     B
+        INX     H
+        DCR     B
+        JNZ     NAME
+        MVI     C,18
+        JMP     LIST
+HELLO:  DB      'Hello from CP/M$'
+OUTFCB: DB      0,'COPY    TXT'
+        DS      24
+WILD:   DB      0,'????????TXT'
+        DS      24
+DIRBUF: DS      128
+").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let mut cpm = Cpm::new(&assembly.to_binary(), "input.txt", dir);
+        let mut out = vec!();
+        let exit = cpm.run(&mut &b""[..], &mut out, Some(100000)).unwrap();
+        let copied = fs::read(dir.join("COPY.TXT")).unwrap();
+        assert_eq!(exit, Exit::WarmBoot);
+        assert_eq!(String::from_utf8(out).unwrap(), "Hello from CP/M INPUT.TXT COPY    TXT INPUT   TXT");
+        assert_eq!(copied.len(), 256);
+        assert!(copied[..200].iter().all(|byte| *byte == b'x') && copied[200..].iter().all(|byte| *byte == 0x1a));
+    }
+
+    #[test]
+    fn reads_the_console_and_exits_through_0000() {
+        let assembly = assemble("\
+        ORG     100H
+        MVI     C,10
+        LXI     D,BUFFER
+        CALL    5
+        LDA     BUFFER+1
+        ADI     '0'
+        MOV     E,A
+        MVI     C,2
+        CALL    5
+        JMP     0
+BUFFER: DB      8
+        DS      9
+").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let mut cpm = Cpm::new(&assembly.to_binary(), "", &env::temp_dir());
+        let mut out = vec!();
+        let exit = cpm.run(&mut &b"abc\nmore"[..], &mut out, None).unwrap();
+        assert_eq!(exit, Exit::WarmBoot);
+        assert_eq!(String::from_utf8(out).unwrap(), "abc\r\n3");
+    }
+
+    //Fields of an FCB at the top of memory wrap round to 0000H: extent 2 is record 256, whose
+    //high byte lands at 0012H
+    #[test]
+    fn fcbs_wrap_at_the_top_of_memory() {
+        let assembly = assemble("\
+        ORG     100H
+        LXI     H,0FFFCH
+        MVI     M,2
+        LXI     D,0FFF0H
+        MVI     C,36
+        CALL    5
+        LDA     12H
+        ADI     '0'
+        MOV     E,A
+        MVI     C,2
+        CALL    5
+        JMP     0
+").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let mut cpm = Cpm::new(&assembly.to_binary(), "", &env::temp_dir());
+        let mut out = vec!();
+        assert_eq!(cpm.run(&mut &b""[..], &mut out, None).unwrap(), Exit::WarmBoot);
+        assert_eq!(String::from_utf8(out).unwrap(), "1");
+    }
+
+    #[test]
+    fn stops_at_undefined_opcodes() {
+        let mut cpm = Cpm::new(&[0x00, 0xdd], "", &env::temp_dir());
+        let exit = cpm.run(&mut &b""[..], &mut vec!(), None).unwrap();
+        assert_eq!(exit, Exit::Fault(ExecutionError::UndefinedOpcode(DecodeError::UndefinedOpcode { addr: 0x0101, byte: 0xdd })));
+        assert_eq!(cpm.cpu().get_pc(), 0x0101);
+    }
+}
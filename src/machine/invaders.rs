@@ -0,0 +1,341 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use cpu::{CPU, Access, Io, Port};
+use error::ExecutionError;
+
+//The 8080 runs at 2MHz and the monitor refreshes at 60Hz
+pub const CYCLES_PER_FRAME: u64 = 2_000_000 / 60;
+//The screen is mounted on its side, so the picture is 224 pixels wide and 256 tall
+pub const WIDTH: usize = 224;
+pub const HEIGHT: usize = 256;
+
+//The ROM set as MAME names it, in load order from 0000H
+pub const ROM_FILES: [&str; 4] = ["invaders.h", "invaders.g", "invaders.f", "invaders.e"];
+const ROM_SIZE: usize = 0x2000;
+const RAM_END: usize = 0x4000;
+const VIDEO_RAM: usize = 0x2400;
+
+//RST 1 arrives when the beam reaches the middle of the screen and RST 2 at vertical blank
+const MID_SCREEN_VECTOR: u8 = 1;
+const VBLANK_VECTOR: u8 = 2;
+
+//The controls, true while held. Port 1 is the cabinet and player one, port 2 player two.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Inputs {
+    pub coin: bool,
+    pub one_player_start: bool,
+    pub two_player_start: bool,
+    pub p1_fire: bool,
+    pub p1_left: bool,
+    pub p1_right: bool,
+    pub p2_fire: bool,
+    pub p2_left: bool,
+    pub p2_right: bool,
+    pub tilt: bool,
+}
+
+//The DIP switches on port 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DipSwitches {
+    //3 to 6
+    pub ships: u8,
+    //Otherwise the extra ship comes at 1500 points
+    pub extra_ship_at_1000: bool,
+    pub show_coin_info: bool,
+}
+
+impl Default for DipSwitches {
+    fn default() -> DipSwitches {
+        DipSwitches { ships: 3, extra_ship_at_1000: false, show_coin_info: true }
+    }
+}
+
+//Everything on the I/O ports: the controls, the DIP switches, the sound latches and the
+//external shift register the game uses to draw sprites at any bit offset
+struct Board {
+    inputs: Inputs,
+    dips: DipSwitches,
+    shift: u16,
+    shift_offset: u8,
+    sounds: [u8; 2],
+}
+
+impl Io for Board {
+    fn input(&mut self, port: Port) -> u8 {
+        let inputs = &self.inputs;
+        match port {
+            0 => 0x0e,
+            1 => inputs.coin as u8 | (inputs.two_player_start as u8) << 1 | (inputs.one_player_start as u8) << 2 | 0x08
+                | (inputs.p1_fire as u8) << 4 | (inputs.p1_left as u8) << 5 | (inputs.p1_right as u8) << 6,
+            2 => (self.dips.ships.clamp(3, 6) - 3) | (inputs.tilt as u8) << 2 | (self.dips.extra_ship_at_1000 as u8) << 3
+                | (inputs.p2_fire as u8) << 4 | (inputs.p2_left as u8) << 5 | (inputs.p2_right as u8) << 6
+                | (!self.dips.show_coin_info as u8) << 7,
+            3 => (self.shift >> (8 - self.shift_offset)) as u8,
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: Port, value: u8) {
+        match port {
+            2 => self.shift_offset = value & 0x7,
+            3 => self.sounds[0] = value,
+            4 => self.shift = self.shift >> 8 | (value as u16) << 8,
+            5 => self.sounds[1] = value,
+            //6 is the watchdog, which a running game keeps resetting
+            _ => {},
+        }
+    }
+}
+
+//One bit per pixel, row by row from the top left, as the player sees the screen
+pub struct Framebuffer {
+    pixels: Vec<bool>,
+}
+
+impl Framebuffer {
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y * WIDTH + x]
+    }
+
+    pub fn lit_pixels(&self) -> usize {
+        self.pixels.iter().filter(|lit| **lit).count()
+    }
+
+    //Binary PPM, white on black
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", WIDTH, HEIGHT)?;
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let level = if self.pixel(x, y) { 0xff } else { 0 };
+                out.write_all(&[level, level, level])?;
+            }
+        }
+        out.flush()
+    }
+}
+
+//Taito's Space Invaders board, run headless: frames are rendered into a framebuffer
+//rather than shown, and the controls are set directly.
+pub struct SpaceInvaders {
+    cpu: CPU,
+    board: Board,
+    framebuffer: Framebuffer,
+    frames: u64,
+}
+
+impl SpaceInvaders {
+    //`rom` is the 8K program, i.e. the four ROM files one after another
+    pub fn new(rom: &[u8]) -> SpaceInvaders {
+        let mut cpu = CPU::empty();
+        let rom = &rom[..rom.len().min(ROM_SIZE)];
+        //Only 14 address lines are decoded, so everything above 3FFFH mirrors the first 16K
+        for mirror in (0..0x10000).step_by(RAM_END) {
+            cpu.load(mirror as u16, rom);
+        }
+        SpaceInvaders {
+            cpu,
+            board: Board { inputs: Inputs::default(), dips: DipSwitches::default(), shift: 0, shift_offset: 0, sounds: [0; 2] },
+            framebuffer: Framebuffer { pixels: vec![false; WIDTH * HEIGHT] },
+            frames: 0,
+        }
+    }
+
+    //Loads the ROM files listed in ROM_FILES from a directory
+    pub fn from_rom_directory(dir: &Path) -> io::Result<SpaceInvaders> {
+        let mut rom = vec!();
+        for name in &ROM_FILES {
+            File::open(dir.join(name))?.read_to_end(&mut rom)?;
+        }
+        Ok(SpaceInvaders::new(&rom))
+    }
+
+    //Runs the CPU for one frame, interrupting it mid-screen and at vertical blank, then renders the screen
+    pub fn run_frame(&mut self) -> Result<(), ExecutionError> {
+        let start = self.cpu.get_cycles();
+        self.run_until(start + CYCLES_PER_FRAME / 2)?;
+        self.cpu.interrupt(MID_SCREEN_VECTOR);
+        self.settle_writes();
+        self.run_until(start + CYCLES_PER_FRAME)?;
+        self.cpu.interrupt(VBLANK_VECTOR);
+        self.settle_writes();
+        self.render();
+        self.frames += 1;
+        Ok(())
+    }
+
+    //Plays a scripted game until `frames` frames have run: a coin goes in after ten seconds
+    //of attract mode, then one player starts and sways from side to side firing. Six ships
+    //keep the player going for a good while.
+    pub fn run_demo(&mut self, frames: u64) -> Result<(), ExecutionError> {
+        self.board.dips.ships = 6;
+        while self.frames < frames {
+            let frame = self.frames;
+            let inputs = &mut self.board.inputs;
+            inputs.coin = (600..610).contains(&frame);
+            inputs.one_player_start = (700..710).contains(&frame);
+            inputs.p1_fire = frame >= 900 && frame % 60 < 5;
+            inputs.p1_left = frame >= 900 && frame % 240 < 120;
+            inputs.p1_right = frame >= 900 && frame % 240 >= 120;
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    //Stops early, partway through the frame, at an opcode the CPU can't run
+    fn run_until(&mut self, cycles: u64) -> Result<(), ExecutionError> {
+        while self.cpu.get_cycles() < cycles {
+            self.cpu.try_step(&mut self.board)?;
+            self.settle_writes();
+        }
+        Ok(())
+    }
+
+    //The CPU sees flat RAM, so put back anything written to ROM and copy RAM writes to every mirror
+    fn settle_writes(&mut self) {
+        let writes: Vec<(u16, u8, u8)> = self.cpu.get_accesses().iter().filter_map(|access| match *access {
+            Access::Write { addr, old, new } => Some((addr, old, new)),
+            _ => None,
+        }).collect();
+        for (addr, old, new) in writes {
+            let offset = addr as usize % RAM_END;
+            if offset < ROM_SIZE {
+                self.cpu.write_memory(addr, old);
+                continue;
+            }
+            for mirror in (0..0x10000).step_by(RAM_END) {
+                self.cpu.write_memory((mirror + offset) as u16, new);
+            }
+        }
+    }
+
+    //Video RAM holds the screen as it lies in the cabinet: each run of 32 bytes is a column
+    //of the upright picture, starting at the bottom, with the low bit of each byte lowest.
+    fn render(&mut self) {
+        for x in 0..WIDTH {
+            for byte in 0..HEIGHT / 8 {
+                let value = self.cpu.read_memory((VIDEO_RAM + x * HEIGHT / 8 + byte) as u16);
+                for bit in 0..8 {
+                    let y = HEIGHT - 1 - (byte * 8 + bit);
+                    self.framebuffer.pixels[y * WIDTH + x] = value & (1 << bit) != 0;
+                }
+            }
+        }
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn inputs_mut(&mut self) -> &mut Inputs {
+        &mut self.board.inputs
+    }
+
+    pub fn dip_switches_mut(&mut self) -> &mut DipSwitches {
+        &mut self.board.dips
+    }
+
+    //The latches on ports 3 and 5 that switch the sound effects on and off
+    pub fn sounds(&self) -> [u8; 2] {
+        self.board.sounds
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assembler::assemble;
+
+    use super::{SpaceInvaders, WIDTH, HEIGHT};
+
+    //A stand-in for the game ROM: shifts a byte through the shift register, copies what comes
+    //out and the player one port into video RAM, counts interrupts and tries to write to ROM
+    #[test]
+    fn models_the_board() {
+        let assembly = assemble("\
+        ORG     0
+        LXI     SP,2400H
+        EI
+        JMP     MAIN
+        ORG     8
+        JMP     MID
+        ORG     10H
+        JMP     VBLANK
+MID:    PUSH    PSW
+        LDA     MIDS
+        INR     A
+        STA     MIDS
+        POP     PSW
+        EI
+        RET
+VBLANK: PUSH    PSW
+        LDA     VBLANKS
+        INR     A
+        STA     VBLANKS
+        POP     PSW
+        EI
+        RET
+MAIN:   MVI     A,0FFH
+        OUT     4
+        MVI     A,81H
+        OUT     4
+        MVI     A,2
+        OUT     2
+        IN      3
+        STA     2400H
+        IN      1
+        STA     2401H
+        STA     6402H
+        STA     0
+        IN      2
+        STA     2403H
+LOOP:   JMP     LOOP
+MIDS    EQU     2000H
+VBLANKS EQU     2001H
+").unwrap_or_else(|errors| panic!("{}", errors[0]));
+        let mut machine = SpaceInvaders::new(&assembly.to_binary());
+        machine.inputs_mut().p1_fire = true;
+        machine.dip_switches_mut().ships = 5;
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        let cpu = machine.cpu();
+        //The second VBlank interrupt has been taken but its handler hasn't run yet
+        assert_eq!(cpu.read_memory(0x2000), 2);
+        assert_eq!(cpu.read_memory(0x2001), 1);
+        assert_eq!(cpu.read_memory(0x2400), 0x07);
+        assert_eq!(cpu.read_memory(0x2401), 0x18);
+        assert_eq!(cpu.read_memory(0x6401), 0x18);
+        assert_eq!(cpu.read_memory(0x2402), 0x18);
+        assert_eq!(cpu.read_memory(0x0000), 0x31);
+        assert_eq!(cpu.read_memory(0x2403), 0x02);
+        assert_eq!(machine.frames(), 2);
+
+        //0x07 in the first byte lights the bottom three pixels of the leftmost column
+        let screen = machine.framebuffer();
+        assert!(screen.pixel(0, HEIGHT - 1) && screen.pixel(0, HEIGHT - 3) && !screen.pixel(0, HEIGHT - 4));
+        assert!(screen.pixel(0, HEIGHT - 12) && screen.pixel(0, HEIGHT - 13));
+        let mut ppm = vec!();
+        screen.write_ppm(&mut ppm).unwrap();
+        assert!(ppm.starts_with(b"P6\n224 256\n255\n"));
+        assert_eq!(ppm.len(), 15 + WIDTH * HEIGHT * 3);
+    }
+
+    #[test]
+    fn plays_the_demo_script() {
+        let mut machine = SpaceInvaders::new(&assemble("LOOP: JMP LOOP").unwrap_or_else(|errors| panic!("{}", errors[0])).to_binary());
+        machine.run_demo(605).unwrap();
+        assert_eq!(machine.frames(), 605);
+        assert_eq!(machine.board.dips.ships, 6);
+        assert!(machine.board.inputs.coin && !machine.board.inputs.one_player_start);
+        machine.run_demo(705).unwrap();
+        assert!(!machine.board.inputs.coin && machine.board.inputs.one_player_start && !machine.board.inputs.p1_fire);
+    }
+}
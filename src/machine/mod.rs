@@ -0,0 +1,4 @@
+//Complete machines built around the CPU: its memory map, I/O devices and interrupts
+pub mod invaders;
+pub mod cpm;
+pub mod altair;
@@ -0,0 +1,101 @@
+use std::io::{self, Read, Write};
+
+use cpu::{CPU, State, MEMORY_SIZE};
+use cpu::condition::Condition;
+use error::LoadError;
+
+//A snapshot is the magic number and a version, then the CPU in this order, all words little endian:
+//A B C D E H L, the flag byte, SP, PC, interrupts enabled, halted, the cycle count (8 bytes),
+//how much of memory the loaded program took up (4 bytes) and then all 64K of memory.
+const MAGIC: &[u8; 8] = b"8080SNAP";
+const VERSION: u16 = 1;
+
+impl CPU {
+    pub fn write_snapshot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let state = self.get_state();
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        out.write_all(&[state.a, state.b, state.c, state.d, state.e, state.h, state.l, state.flags.to_byte()])?;
+        out.write_all(&state.sp.to_le_bytes())?;
+        out.write_all(&state.pc.to_le_bytes())?;
+        out.write_all(&[state.interrupts_enabled as u8, state.halted as u8])?;
+        out.write_all(&state.cycles.to_le_bytes())?;
+        out.write_all(&(self.rom_size as u32).to_le_bytes())?;
+        out.write_all(&self.memory)
+    }
+
+    pub fn read_snapshot<R: Read>(input: &mut R) -> Result<CPU, LoadError> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(LoadError::NotASnapshot);
+        }
+        let version = u16::from_le_bytes(read_array(input)?);
+        if version != VERSION {
+            return Err(LoadError::UnsupportedSnapshotVersion(version));
+        }
+        let [a, b, c, d, e, h, l, flags] = read_array(input)?;
+        let sp = u16::from_le_bytes(read_array(input)?);
+        let pc = u16::from_le_bytes(read_array(input)?);
+        let [interrupts_enabled, halted] = read_array(input)?;
+        let cycles = u64::from_le_bytes(read_array(input)?);
+        let rom_size = u32::from_le_bytes(read_array(input)?) as usize;
+        let mut cpu = CPU::empty();
+        input.read_exact(&mut cpu.memory)?;
+        cpu.rom_size = rom_size.min(MEMORY_SIZE);
+        cpu.set_state(&State {
+            a, b, c, d, e, h, l,
+            flags: Condition::from_byte(flags),
+            sp,
+            pc,
+            interrupts_enabled: interrupts_enabled != 0,
+            halted: halted != 0,
+            cycles,
+        });
+        Ok(cpu)
+    }
+}
+
+fn read_array<R: Read, const N: usize>(input: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::{CPU, NoIo};
+    use cpu::register::{Register, RegisterPair};
+    use error::LoadError;
+    use testing::cpu_from_assembly;
+
+    #[test]
+    fn snapshots_round_trip() {
+        let (mut cpu, _) = cpu_from_assembly("MVI A,42H\nSTC\nEI\nHLT");
+        cpu.set_register_pair(RegisterPair::SP, 0x1234);
+        cpu.write_memory(0xffff, 0x99);
+        let mut io = NoIo;
+        for _ in 0..4 {
+            cpu.try_step(&mut io).unwrap();
+        }
+        let mut snapshot = vec!();
+        cpu.write_snapshot(&mut snapshot).unwrap();
+        let restored = CPU::read_snapshot(&mut snapshot.as_slice()).unwrap();
+        assert_eq!(restored.get_state(), cpu.get_state());
+        assert!(restored.is_halted() && restored.interrupts_enabled());
+        assert_eq!(restored.get_register(&Register::A), 0x42);
+        assert_eq!(restored.read_memory(0xffff), 0x99);
+
+        snapshot[8] = 2;
+        let error = CPU::read_snapshot(&mut snapshot.as_slice()).err().unwrap();
+        assert_eq!(error.to_string(), "snapshot version 2 isn't supported, only version 1");
+        match CPU::read_snapshot(&mut &b"8080SNAP\x01"[..]) {
+            Err(LoadError::Io(_)) => {},
+            other => panic!("{:?}", other.map(|cpu| cpu.get_state())),
+        }
+        match CPU::read_snapshot(&mut &b"8085SNAP\x01\x00"[..]) {
+            Err(LoadError::NotASnapshot) => {},
+            other => panic!("{:?}", other.map(|cpu| cpu.get_state())),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//Fixtures shared by the tests
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use assembler::{self, Assembly};
+use cpu::CPU;
+
+//A CPU with `source` assembled into memory at its origin and ready to run from there
+pub fn cpu_from_assembly(source: &str) -> (CPU, Assembly) {
+    let assembly = assembler::assemble(source).unwrap_or_else(|errors| panic!("{}", errors[0]));
+    let mut cpu = CPU::empty();
+    cpu.load(assembly.origin(), &assembly.to_binary());
+    cpu.set_pc(assembly.origin());
+    (cpu, assembly)
+}
+
+//A scratch directory of the test's own, removed however the test ends
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let dir = env::temp_dir().join(format!("eightyeightyemu-test-{}-{}", process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+            if fs::create_dir(&dir).is_ok() {
+                return TempDir(dir);
+            }
+        }
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, Write};
+
+use cpu::{CPU, Io};
+use cpu::instruction::format_byte;
+use error::Error;
+use cpu::register::{Register, RegisterPair};
+
+//Trace lines follow the layout common 8080 reference emulators log in, showing the state
+//from before the instruction runs and the four bytes at the PC:
+//PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0	(C3 AB 01 00)	JMP 01ABH
+//The mnemonic is our own extra column, so `cut -f1,2` leaves a line that diffs against a reference log.
+pub fn write_trace_line<W: Write>(cpu: &CPU, out: &mut W) -> io::Result<()> {
+    let pc = cpu.get_pc();
+    let af = (cpu.get_register(&Register::A) as u16) << 8 | cpu.get_flags().to_byte() as u16;
+    let bytes: Vec<String> = (0..4).map(|offset| format!("{:02X}", cpu.read_memory(pc.wrapping_add(offset)))).collect();
+    //a byte the CPU can't run is shown as data, as the disassembler shows it
+    let instruction = match cpu.instruction_at(pc) {
+        Ok(instruction) => instruction.to_string(),
+        Err(_) => format!("DB {}", format_byte(cpu.read_memory(pc))),
+    };
+    writeln!(out, "PC: {:04X}, AF: {:04X}, BC: {:04X}, DE: {:04X}, HL: {:04X}, SP: {:04X}, CYC: {}\t({})\t{}",
+        pc, af, cpu.get_register_pair(RegisterPair::BC), cpu.get_register_pair(RegisterPair::DE),
+        cpu.get_register_pair(RegisterPair::HL), cpu.get_register_pair(RegisterPair::SP), cpu.get_cycles(),
+        bytes.join(" "), instruction)
+}
+
+//Runs until the CPU halts or `limit` instructions have run, tracing each one, and returns how many ran.
+//An opcode the CPU can't run stops the trace with its line written, and is returned as the error.
+pub fn run_traced<W: Write>(cpu: &mut CPU, io: &mut dyn Io, out: &mut W, limit: u64) -> Result<u64, Error> {
+    let mut count = 0;
+    while count < limit && !cpu.is_halted() {
+        write_trace_line(cpu, out)?;
+        if let Err(error) = cpu.try_step(io) {
+            out.flush()?;
+            return Err(error.into());
+        }
+        count += 1;
+    }
+    out.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use cpu::NoIo;
+    use error::{DecodeError, Error, ExecutionError};
+    use testing::cpu_from_assembly;
+
+    use super::run_traced;
+
+    #[test]
+    fn traces_state_before_each_instruction() {
+        let (mut cpu, _) = cpu_from_assembly("\
+        ORG     0
+        LXI     SP,100H
+        MVI     A,0FFH
+        INR     A
+        HLT
+");
+        let mut out = vec!();
+        assert_eq!(run_traced(&mut cpu, &mut NoIo, &mut out, 100).unwrap(), 4);
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines, vec!(
+            "PC: 0000, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0000, CYC: 0\t(31 00 01 3E)\tLXI SP,0100H",
+            "PC: 0003, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: 0100, CYC: 10\t(3E FF 3C 76)\tMVI A,0FFH",
+            "PC: 0005, AF: FF02, BC: 0000, DE: 0000, HL: 0000, SP: 0100, CYC: 17\t(3C 76 00 00)\tINR A",
+            "PC: 0006, AF: 0056, BC: 0000, DE: 0000, HL: 0000, SP: 0100, CYC: 22\t(76 00 00 00)\tHLT",
+        ));
+    }
+
+    #[test]
+    fn stops_at_undefined_opcodes() {
+        let (mut cpu, _) = cpu_from_assembly("MVI A,1\nDB 0DDH\nHLT");
+        let mut out = vec!();
+        match run_traced(&mut cpu, &mut NoIo, &mut out, 100) {
+            Err(Error::Execution(ExecutionError::UndefinedOpcode(DecodeError::UndefinedOpcode { addr: 0x0002, byte: 0xdd }))) => {},
+            other => panic!("{:?}", other),
+        }
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 2);
+        assert!(out.ends_with("\t(DD 76 00 00)\tDB 0DDH\n"), "{}", out);
+        assert_eq!(cpu.get_pc(), 0x0002);
+    }
+}
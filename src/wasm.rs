@@ -0,0 +1,228 @@
+//The emulator as a WebAssembly module for the browser, exported as plain functions over
+//numbers and pointers so any WASM runtime can drive it without generated glue. Build it with
+//  cargo build --lib --release --target wasm32-unknown-unknown --features wasm
+//From JavaScript: emu_alloc a buffer, copy the ROM into the module's memory there and pass it
+//to emu_load, then call emu_run and read the machine back through the other exports. The
+//pointers handed back view the module's memory directly, so wrap them in a fresh Uint8Array
+//after each call that might have grown it.
+//Every function taking an emulator expects one from emu_new that hasn't been through emu_free,
+//and buffers must be ones emu_alloc returned, with the length they were allocated with.
+#![allow(clippy::missing_safety_doc)]
+
+use std::slice;
+
+use cpu::{CPU, NoIo};
+use exports;
+use machine::invaders::{self, SpaceInvaders};
+
+//The machines a playground can run, as emu_new numbers them
+pub const PROFILE_PLAIN: u32 = 0;
+pub const PROFILE_INVADERS: u32 = 1;
+
+//emu_register numbers the registers as the C ABI does
+pub use exports::{REGISTER_FLAGS, REGISTER_SP, REGISTER_PC};
+
+enum Machine {
+    Plain(CPU),
+    Invaders(Box<SpaceInvaders>),
+}
+
+pub struct Emulator {
+    machine: Machine,
+    //The current frame as RGBA, ready for a canvas; empty for machines without a screen
+    pixels: Vec<u8>,
+}
+
+impl Emulator {
+    fn cpu(&self) -> &CPU {
+        match self.machine {
+            Machine::Plain(ref cpu) => cpu,
+            Machine::Invaders(ref invaders) => invaders.cpu(),
+        }
+    }
+}
+
+//Returns null for a profile that doesn't exist
+#[no_mangle]
+pub extern "C" fn emu_new(profile: u32) -> *mut Emulator {
+    let machine = match profile {
+        PROFILE_PLAIN => Machine::Plain(CPU::empty()),
+        PROFILE_INVADERS => Machine::Invaders(Box::new(SpaceInvaders::new(&[]))),
+        _ => return std::ptr::null_mut(),
+    };
+    Box::into_raw(Box::new(Emulator { machine, pixels: vec!() }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_free(emu: *mut Emulator) {
+    if !emu.is_null() {
+        drop(Box::from_raw(emu));
+    }
+}
+
+//Room in the module's memory for JavaScript to copy a ROM into
+#[no_mangle]
+pub extern "C" fn emu_alloc(len: usize) -> *mut u8 {
+    let mut buffer = vec![0u8; len].into_boxed_slice();
+    let ptr = buffer.as_mut_ptr();
+    std::mem::forget(buffer);
+    ptr
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_dealloc(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+//A plain CPU loads the bytes at `addr` and starts there. The invaders board takes them as its
+//8K ROM from 0000H, whatever `addr` is, and starts over from power on.
+#[no_mangle]
+pub unsafe extern "C" fn emu_load(emu: *mut Emulator, addr: u16, bytes: *const u8, len: usize) {
+    let emu = &mut *emu;
+    let bytes = slice::from_raw_parts(bytes, len);
+    match emu.machine {
+        Machine::Plain(ref mut cpu) => {
+            cpu.load(addr, bytes);
+            cpu.set_pc(addr);
+        },
+        Machine::Invaders(ref mut invaders) => **invaders = SpaceInvaders::new(bytes),
+    }
+}
+
+//Runs for at least `cycles` cycles and returns how many it took. A plain CPU stops early once it
+//halts; the invaders board only stops between frames, so it runs whole frames. Either stops
+//early at an opcode the 8080 doesn't define.
+#[no_mangle]
+pub unsafe extern "C" fn emu_run(emu: *mut Emulator, cycles: u64) -> u64 {
+    match (*emu).machine {
+        Machine::Plain(ref mut cpu) => exports::run(cpu, &mut NoIo, cycles),
+        Machine::Invaders(ref mut invaders) => {
+            let start = invaders.cpu().get_cycles();
+            while invaders.cpu().get_cycles() - start < cycles {
+                if invaders.run_frame().is_err() {
+                    break;
+                }
+            }
+            invaders.cpu().get_cycles() - start
+        },
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_cycles(emu: *const Emulator) -> u64 {
+    (*emu).cpu().get_cycles()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_halted(emu: *const Emulator) -> bool {
+    (*emu).cpu().is_halted()
+}
+
+//0 for a register number that doesn't exist
+#[no_mangle]
+pub unsafe extern "C" fn emu_register(emu: *const Emulator, register: u32) -> u32 {
+    exports::get_register((*emu).cpu(), register) as u32
+}
+
+//8 bit registers take the low byte of value; an unknown register, or any register on a machine
+//other than a plain CPU, is left alone
+#[no_mangle]
+pub unsafe extern "C" fn emu_set_register(emu: *mut Emulator, register: u32, value: u32) {
+    if let Machine::Plain(ref mut cpu) = (*emu).machine {
+        exports::set_register(cpu, register, value as u16);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_read_memory(emu: *const Emulator, addr: u16) -> u8 {
+    (*emu).cpu().read_memory(addr)
+}
+
+//All 64K of memory
+#[no_mangle]
+pub unsafe extern "C" fn emu_memory(emu: *const Emulator) -> *const u8 {
+    (*emu).cpu().memory.as_ptr()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_framebuffer_width(emu: *const Emulator) -> u32 {
+    match (*emu).machine {
+        Machine::Invaders(_) => invaders::WIDTH as u32,
+        Machine::Plain(_) => 0,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn emu_framebuffer_height(emu: *const Emulator) -> u32 {
+    match (*emu).machine {
+        Machine::Invaders(_) => invaders::HEIGHT as u32,
+        Machine::Plain(_) => 0,
+    }
+}
+
+//The last frame rendered, width * height RGBA pixels row by row from the top left.
+//Null for machines without a screen.
+#[no_mangle]
+pub unsafe extern "C" fn emu_framebuffer(emu: *mut Emulator) -> *const u8 {
+    let emu = &mut *emu;
+    let framebuffer = match emu.machine {
+        Machine::Invaders(ref invaders) => invaders.framebuffer(),
+        Machine::Plain(_) => return std::ptr::null(),
+    };
+    emu.pixels.clear();
+    for y in 0..invaders::HEIGHT {
+        for x in 0..invaders::WIDTH {
+            let level = if framebuffer.pixel(x, y) { 0xff } else { 0 };
+            emu.pixels.extend_from_slice(&[level, level, level, 0xff]);
+        }
+    }
+    emu.pixels.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use assembler::assemble;
+
+    use super::*;
+
+    unsafe fn load(emu: *mut Emulator, addr: u16, program: &[u8]) {
+        let buffer = emu_alloc(program.len());
+        slice::from_raw_parts_mut(buffer, program.len()).copy_from_slice(program);
+        emu_load(emu, addr, buffer, program.len());
+        emu_dealloc(buffer, program.len());
+    }
+
+    #[test]
+    fn drives_machines_through_the_exports() {
+        unsafe {
+            assert!(emu_new(7).is_null());
+
+            let emu = emu_new(PROFILE_PLAIN);
+            load(emu, 0x100, &assemble("ORG 100H\nMVI A,42H\nLXI H,2000H\nMOV M,A\nHLT").unwrap().to_binary());
+            assert_eq!(emu_register(emu, REGISTER_PC), 0x100);
+            assert_eq!(emu_run(emu, 1_000_000), 7 + 10 + 7 + 7);
+            assert!(emu_halted(emu));
+            assert_eq!(emu_register(emu, 7), 0x42);
+            assert_eq!(emu_register(emu, 6), 0x42);
+            assert_eq!(emu_read_memory(emu, 0x2000), 0x42);
+            assert_eq!(*emu_memory(emu).offset(0x101), 0x42);
+            emu_set_register(emu, REGISTER_SP, 0xf000);
+            assert_eq!(emu_register(emu, REGISTER_SP), 0xf000);
+            assert!(emu_framebuffer(emu).is_null());
+            assert_eq!(emu_framebuffer_width(emu), 0);
+            emu_free(emu);
+
+            //Lights the top left pixel: the last byte of the first column, high bit
+            let emu = emu_new(PROFILE_INVADERS);
+            load(emu, 0, &assemble("MVI A,80H\nSTA 241FH\nLOOP: JMP LOOP").unwrap().to_binary());
+            assert!(emu_run(emu, 1) >= invaders::CYCLES_PER_FRAME);
+            let (width, height) = (emu_framebuffer_width(emu) as usize, emu_framebuffer_height(emu) as usize);
+            let pixels = slice::from_raw_parts(emu_framebuffer(emu), width * height * 4);
+            assert_eq!(&pixels[..8], &[0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0xff]);
+            assert_eq!(pixels.iter().filter(|level| **level == 0xff).count(), width * height + 3);
+            emu_free(emu);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//Drives the emulator's command line the way a build script would
+
+use std::fs;
+use std::process::{Command, Output, Stdio};
+
+mod common;
+
+use common::TempDir;
+
+fn emulator(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_eightyeightyemu"))
+        .args(args)
+        .output()
+        .expect("Unable to run the emulator.")
+}
+
+#[test]
+fn disassembles_a_range_to_stdout() {
+    //MVI A,41H; OUT 1; HLT loaded where a CP/M program would be
+    let dir = TempDir::new();
+    let path = dir.write("prog.bin", &[0x3e, 0x41, 0xd3, 0x01, 0x76]);
+    let path = path.to_str().unwrap();
+
+    let output = emulator(&["disasm", "-l", "100H", "--start", "102H", path]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "0102  D3 01     OUT 01H\n0104  76        HLT\n");
+
+    let output = emulator(&["run", "-l", "100", path]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "OUT 01H: 41H\nHalted after 3 instructions, 24 cycles.\n");
+
+    let output = emulator(&["disasm", "--trace", "out", path]);
+    assert_eq!(output.status.code(), Some(2));
+    let output = emulator(&["disasm", &dir.path("missing.bin").to_string_lossy()]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn reports_missing_files_instead_of_panicking() {
+    let dir = TempDir::new();
+    let missing = |name: &str| dir.path(name).to_string_lossy().into_owned();
+
+    for mut args in [vec!("run", "-m", "plain"), vec!("run", "-m", "altair"), vec!("run", "-m", "cpm"), vec!("link")] {
+        let file = missing(if args[0] == "link" { "missing.rel" } else { "missing.com" });
+        args.push(&file);
+        let output = emulator(&args);
+        let errors = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(1), "{:?}: {}", args, errors);
+        assert!(errors.contains(&file) && !errors.contains("panicked"), "{:?}: {}", args, errors);
+    }
+}
+
+#[test]
+fn writes_source_at_the_load_address() {
+    let dir = TempDir::new();
+    let path = dir.write("prog.com", &[0x3e, 0x41, 0xd3, 0x01, 0x76]);
+
+    let output = emulator(&["disasm", "--syntax", "source", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    let source = String::from_utf8_lossy(&output.stdout);
+    assert!(source.contains("ORG     0100H"), "{}", source);
+    assert!(source.contains("; Disassembly of 5 bytes"), "{}", source);
+}
+
+#[test]
+fn stops_at_undefined_opcodes() {
+    let dir = TempDir::new();
+    let path = dir.write("fault.bin", &[0x00, 0xdd, 0x76]);
+
+    let output = emulator(&["run", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "Stopped after 1 instructions, 4 cycles: undefined opcode DDH at 0001H\n");
+    let trace = dir.path("fault.trace");
+    let output = emulator(&["run", "--trace", trace.to_str().unwrap(), path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(fs::read_to_string(&trace).unwrap().lines().count(), 2);
+}
+
+#[test]
+fn stops_quietly_once_nothing_reads_its_output() {
+    let dir = TempDir::new();
+    let path = dir.write("big.bin", &[0; 0x8000]);
+
+    //as `disasm big.bin 2>&1 | head -1` leaves it, with both streams closed after the first read
+    let mut child = Command::new(env!("CARGO_BIN_EXE_eightyeightyemu"))
+        .args(["disasm", path.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Unable to run the emulator.");
+    drop(child.stdout.take());
+    drop(child.stderr.take());
+    assert_eq!(child.wait().unwrap().code(), Some(1));
+}
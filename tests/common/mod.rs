@@ -0,0 +1,39 @@
+//Fixtures shared by the integration tests
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//A scratch directory of the test's own, removed however the test ends
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let dir = env::temp_dir().join(format!("eightyeightyemu-test-{}-{}", process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+            if fs::create_dir(&dir).is_ok() {
+                return TempDir(dir);
+            }
+        }
+    }
+
+    //Where a file called `name` goes, whether or not it's there
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+
+    pub fn write(&self, name: &str, contents: &[u8]) -> PathBuf {
+        let path = self.path(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,98 @@
+//Runs the well known 8080 diagnostics as CP/M programs through the emulator and checks
+//they report success. The programs themselves aren't distributed with the crate, so these
+//tests only run when asked for: put TST8080.COM, CPUTEST.COM, 8080PRE.COM, 8080EXM.COM and
+//CPUDIAG.COM in tests/roms, or point EXERCISER_DIR at wherever they are, then
+//cargo test --release -- --ignored
+//A program that can't be found fails its test. 8080EXM runs for billions of cycles.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+mod common;
+
+use common::TempDir;
+
+fn exerciser_dir() -> PathBuf {
+    match env::var_os("EXERCISER_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms"),
+    }
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    let dir = exerciser_dir();
+    [name.to_string(), name.to_ascii_lowercase()].iter()
+        .map(|file| dir.join(file))
+        .find(|path| path.is_file())
+}
+
+//Runs a .COM file under the emulator and returns everything it printed
+fn run_com(path: &Path) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_eightyeightyemu"))
+        .arg("run")
+        .arg(path)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Unable to run the emulator.");
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    assert!(output.status.success(), "{}\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    stdout
+}
+
+fn run_exerciser(name: &str, success: &str, failure: &str) {
+    let path = find_program(name).unwrap_or_else(|| panic!("{} isn't in {}", name, exerciser_dir().display()));
+    let output = run_com(&path);
+    assert!(output.contains(success), "{}", output);
+    assert!(!output.contains(failure), "{}", output);
+    assert!(output.contains("WarmBoot after"), "{}", output);
+}
+
+#[test]
+#[ignore]
+fn tst8080() {
+    run_exerciser("TST8080.COM", "CPU IS OPERATIONAL", "CPU HAS FAILED");
+}
+
+#[test]
+#[ignore]
+fn cpudiag() {
+    run_exerciser("CPUDIAG.COM", "CPU IS OPERATIONAL", "CPU HAS FAILED");
+}
+
+#[test]
+#[ignore]
+fn cputest() {
+    run_exerciser("CPUTEST.COM", "CPU TESTS OK", "ERROR");
+}
+
+#[test]
+#[ignore]
+fn preliminary_exerciser() {
+    run_exerciser("8080PRE.COM", "8080 Preliminary tests complete", "ERROR");
+}
+
+#[test]
+#[ignore]
+fn instruction_exerciser() {
+    run_exerciser("8080EXM.COM", "Tests complete", "ERROR");
+}
+
+//Always runs, so the plumbing the exercisers rely on is tested even without them: prints
+//a string through BDOS function 9 and a character through function 2, then returns to CP/M
+#[test]
+fn runs_com_files_through_the_bdos() {
+    let program = [
+        0x11, 0x0f, 0x01,   //LXI D,010FH, the message just after the code
+        0x0e, 0x09,         //MVI C,9
+        0xcd, 0x05, 0x00,   //CALL 5
+        0x1e, 0x21,         //MVI E,'!'
+        0x0e, 0x02,         //MVI C,2
+        0xc3, 0x05, 0x00,   //JMP 5, so the BDOS returns straight to CP/M
+    ];
+    let mut program = program.to_vec();
+    program.extend_from_slice(b"OPERATIONAL$");
+    let dir = TempDir::new();
+    let output = run_com(&dir.write("HELLO.COM", &program));
+    assert!(output.contains("OPERATIONAL!\nWarmBoot after"), "{}", output);
+}
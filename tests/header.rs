@@ -0,0 +1,12 @@
+//The committed C header has to match what src/ffi.rs exports
+
+use std::fs;
+
+#[path = "../examples/gen-header/header.rs"]
+mod header;
+
+#[test]
+fn header_matches_the_exports() {
+    let written = fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/include/eightyeightyemu.h")).unwrap_or_default();
+    assert!(written == header::generate_header(), "include/eightyeightyemu.h is out of date; run `cargo run --example gen-header`");
+}